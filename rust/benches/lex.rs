@@ -0,0 +1,41 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use lib::display::BrowserWidget;
+use lib::http::lex;
+
+// A page of roughly a megabyte with a tag every few words
+fn large_page() -> String {
+    let paragraph = "<p>The quick <b>brown</b> fox jumps over the <i>lazy</i> dog.</p>\n";
+    format!(
+        "<html><body>{}</body></html>",
+        paragraph.repeat(1024 * 1024 / paragraph.len())
+    )
+}
+
+fn bench_lex(c: &mut Criterion) {
+    let page = large_page();
+    let mut group = c.benchmark_group("lex");
+    group.throughput(Throughput::Bytes(page.len() as u64));
+    group.bench_function("large page", |b| b.iter(|| lex(page.as_bytes())));
+    group.finish();
+
+    // Long runs of text between tags are where copying by slice pays off
+    let text = "lorem ipsum dolor sit amet ".repeat(40_000);
+    let mut group = c.benchmark_group("lex");
+    group.throughput(Throughput::Bytes(text.len() as u64));
+    group.bench_function("plain text", |b| b.iter(|| lex(text.as_bytes())));
+    group.finish();
+}
+
+fn bench_layout(c: &mut Criterion) {
+    let text = lex(large_page().as_bytes());
+    let mut group = c.benchmark_group("layout");
+    group.throughput(Throughput::Bytes(text.len() as u64));
+    group.sample_size(20);
+    group.bench_function("large page", |b| {
+        b.iter(|| BrowserWidget::new(text.clone()))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_lex, bench_layout);
+criterion_main!(benches);
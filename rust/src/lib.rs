@@ -1,19 +1,29 @@
 pub mod http {
+    use std::cell::RefCell;
     use std::collections::HashMap;
     use std::env;
     use std::fmt;
+    use std::fs;
     use std::io::{self, BufRead, BufReader, Read, Write};
-    use std::net::TcpStream;
+    use std::net::{IpAddr, Ipv6Addr, TcpStream, ToSocketAddrs};
+    use std::path::Path;
     use std::sync::Arc;
+    use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
     use flate2::bufread::{DeflateDecoder, GzDecoder};
     use regex::bytes::Regex;
     use rustls::{ClientConfig, ClientSession, StreamOwned};
     use webpki::DNSNameRef;
 
+    // Redirects followed for one request before giving up
+    pub const MAX_REDIRECTS: usize = 10;
+
+    // How long to wait to connect, and for each read and write
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
     enum Stream {
         Tcp(TcpStream),
-        Tls(StreamOwned<ClientSession, TcpStream>),
+        Tls(Box<StreamOwned<ClientSession, TcpStream>>),
     }
 
     impl Read for Stream {
@@ -48,17 +58,37 @@ pub mod http {
         }
     }
 
+    // Reports the number of body bytes received so far, along with the
+    // expected total from Content-Length when the server sent one.
+    struct ProgressReader<'a, R> {
+        inner: R,
+        received: usize,
+        total: Option<usize>,
+        progress: &'a mut dyn FnMut(usize, Option<usize>),
+    }
+
+    impl<'a, R: Read> Read for ProgressReader<'a, R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = self.inner.read(buf)?;
+            if len > 0 {
+                self.received += len;
+                (self.progress)(self.received, self.total);
+            }
+            Ok(len)
+        }
+    }
+
     #[derive(Debug)]
-    enum ContentEncoding {
+    pub enum ContentEncoding {
         Gzip,
-        Compress,
         Deflate,
         Identity,
         Brotli,
+        Zstd,
     }
 
     #[derive(Debug)]
-    struct EncodingError;
+    pub struct EncodingError;
 
     impl std::str::FromStr for ContentEncoding {
         type Err = EncodingError;
@@ -66,14 +96,14 @@ pub mod http {
         fn from_str(s: &str) -> Result<Self, Self::Err> {
             if "gzip".eq_ignore_ascii_case(s) {
                 Ok(Self::Gzip)
-            } else if "compress".eq_ignore_ascii_case(s) {
-                Ok(Self::Compress)
             } else if "deflate".eq_ignore_ascii_case(s) {
                 Ok(Self::Deflate)
             } else if "identity".eq_ignore_ascii_case(s) {
                 Ok(Self::Identity)
             } else if "br".eq_ignore_ascii_case(s) {
                 Ok(Self::Brotli)
+            } else if "zstd".eq_ignore_ascii_case(s) {
+                Ok(Self::Zstd)
             } else {
                 Err(EncodingError)
             }
@@ -87,17 +117,33 @@ pub mod http {
         Some((split.next()?, split.next()?))
     }
 
-    fn decompressor<'a, R: BufRead + 'a>(
+    // Wrap reader so that reading yields the decoded body. Any BufRead will
+    // do, including a plain byte slice, so callers holding the body in
+    // memory need no extra buffer.
+    pub fn decompress<'a, R: BufRead + 'a>(
         reader: R,
         encoding: ContentEncoding,
-    ) -> Box<dyn Read + 'a> {
+    ) -> io::Result<Box<dyn Read + 'a>> {
         use ContentEncoding::*;
-        match encoding {
+        Ok(match encoding {
+            // GzDecoder checks the CRC32 and ISIZE trailer and fails the
+            // read on a mismatch, which request reports as MalformedResponse
             Gzip => Box::new(GzDecoder::new(reader)),
             Deflate => Box::new(DeflateDecoder::new(reader)),
+            Brotli => Box::new(brotli::Decompressor::new(reader, 4096)),
+            // Fails when the decoder cannot be allocated
+            Zstd => Box::new(zstd::Decoder::with_buffer(reader)?),
             Identity => Box::new(reader),
-            _ => unimplemented!(),
-        }
+        })
+    }
+
+    // Parse a content or transfer coding that decompress can undo.
+    // compress (LZW) is not one of them.
+    fn decodable(coding: &str) -> Result<ContentEncoding, RequestError> {
+        coding
+            .trim()
+            .parse()
+            .or(Err(RequestError::UnsupportedEncoding))
     }
 
     #[derive(Debug)]
@@ -106,9 +152,19 @@ pub mod http {
         MalformedUrl,
         UnknownScheme(String),
         ConnectionError,
+        // The connection failed while the request was being sent
+        SendError,
         StatusError(String, String),
         MalformedResponse,
         UnsupportedEncoding,
+        TooManyRedirects,
+        InsecureRedirect(String),
+        FileError(String),
+        Timeout,
+        #[cfg(feature = "json")]
+        NotJson(String),
+        #[cfg(feature = "json")]
+        InvalidJson(String),
     }
 
     impl fmt::Display for RequestError {
@@ -120,363 +176,3785 @@ pub mod http {
                     write!(f, "Unknown scheme: {}", scheme)
                 }
                 RequestError::ConnectionError => f.write_str("Connection error"),
+                RequestError::SendError => f.write_str("Connection lost while sending request"),
                 RequestError::StatusError(status, reason) => {
                     write!(f, "Status error: {} {}", status, reason)
                 }
                 RequestError::MalformedResponse => f.write_str("Malformed response"),
                 RequestError::UnsupportedEncoding => f.write_str("Unsupported encoding"),
+                RequestError::TooManyRedirects => f.write_str("Too many redirects"),
+                RequestError::InsecureRedirect(url) => {
+                    write!(f, "Refused insecure redirect to {}", url)
+                }
+                RequestError::FileError(err) => write!(f, "Cannot read file {}", err),
+                RequestError::Timeout => f.write_str("Connection timed out"),
+                #[cfg(feature = "json")]
+                RequestError::NotJson(content_type) => {
+                    write!(f, "Expected JSON, got {}", content_type)
+                }
+                #[cfg(feature = "json")]
+                RequestError::InvalidJson(err) => write!(f, "Invalid JSON: {}", err),
             }
         }
     }
 
     impl std::error::Error for RequestError {}
 
-    pub fn request(url: &str) -> Result<(HashMap<String, String>, Vec<u8>), RequestError> {
-        // 1. Parse scheme
-        let (scheme, url) = split2(url, ":").unwrap_or(("https", url));
-        let default_port = match scheme {
-            "http" => 80,
-            "https" => 443,
-            "data" => {
-                // Exercise data scheme
-                let (content_type, body) = split2(url, ",").ok_or(RequestError::MalformedUrl)?;
-                let mut headers = HashMap::new();
-                headers.insert("content-type".to_owned(), content_type.to_owned());
-                return Ok((headers, body.as_bytes().to_vec()));
-            }
-            _ => return Err(RequestError::UnknownScheme(scheme.to_string())),
-        };
-        let url = url.strip_prefix("//").unwrap_or(url);
+    impl RequestError {
+        // Whether the request may have reached the server before it failed,
+        // as retriable takes. Only failures to connect, or to make sense of
+        // the URL, are certain to have sent nothing. A Timeout may have come
+        // before or after sending, so it counts as sent.
+        pub fn sent(&self) -> bool {
+            !matches!(
+                self,
+                RequestError::Unreachable
+                    | RequestError::MalformedUrl
+                    | RequestError::UnknownScheme(_)
+                    | RequestError::ConnectionError
+                    | RequestError::FileError(_)
+            )
+        }
+    }
 
-        // 2. Parse host
-        let (host, path) = split2(url, "/").ok_or(RequestError::MalformedUrl)?;
-        let path = format!("/{}", path);
+    // Response headers in the order received. Names are lowercase and
+    // compared case-insensitively, and a name may repeat, as Set-Cookie
+    // often does.
+    #[derive(Debug, Clone, Default, PartialEq)]
+    pub struct Headers {
+        entries: Vec<(String, String)>,
+    }
 
-        // 3. Parse port
-        let (host, port) = if host.contains(':') {
-            let (host, port) = split2(host, ":").ok_or(RequestError::Unreachable)?;
-            let port = port.parse().or(Err(RequestError::MalformedUrl))?;
-            (host, port)
-        } else {
-            (host, default_port)
-        };
+    impl Headers {
+        pub fn new() -> Headers {
+            Headers::default()
+        }
 
-        // 4. Connect
-        let stream = TcpStream::connect((host, port)).or(Err(RequestError::ConnectionError))?;
-        let mut stream = if scheme != "https" {
-            Stream::Tcp(stream)
-        } else {
-            let mut config = ClientConfig::new();
-            config
-                .root_store
-                .add_server_trust_anchors(&webpki_roots::TLS_SERVER_ROOTS);
-            let host = DNSNameRef::try_from_ascii_str(host).or(Err(RequestError::MalformedUrl))?;
-            let client = ClientSession::new(&Arc::new(config), host);
-            let stream = StreamOwned::new(client, stream);
-            Stream::Tls(stream)
-        };
+        // The first value of name
+        pub fn get(&self, name: &str) -> Option<&String> {
+            self.entries
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value)
+        }
+
+        // Every value of name, in the order received
+        pub fn get_all(&self, name: &str) -> Vec<&str> {
+            self.entries
+                .iter()
+                .filter(|(key, _)| key.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.as_str())
+                .collect()
+        }
+
+        pub fn contains_key(&self, name: &str) -> bool {
+            self.get(name).is_some()
+        }
+
+        // Add value for name, after any it already has
+        pub fn append(&mut self, name: &str, value: &str) {
+            self.entries
+                .push((name.to_ascii_lowercase(), value.to_string()));
+        }
+
+        // Make value the only value of name
+        pub fn insert(&mut self, name: String, value: String) {
+            self.remove(&name);
+            self.append(&name, &value);
+        }
 
-        // 5. Send request
-        write!(
-            stream,
-            "GET {} HTTP/1.1\r\n\
-            Host: {}\r\n\
-            Connction: close\r\n\
-            User-Agent: Mozilla/5.0 ({})\r\n\
-            Accept-Encoding: gzip,deflate\r\n\
-            \r\n",
-            path,
-            host,
-            env::consts::OS
+        // Remove every value of name, returning the first
+        pub fn remove(&mut self, name: &str) -> Option<String> {
+            let first = self.get(name).cloned();
+            self.entries
+                .retain(|(key, _)| !key.eq_ignore_ascii_case(name));
+            first
+        }
+
+        pub fn iter(&self) -> impl Iterator<Item = (&String, &String)> {
+            self.entries.iter().map(|(name, value)| (name, value))
+        }
+    }
+
+    impl std::ops::Index<&str> for Headers {
+        type Output = String;
+
+        fn index(&self, name: &str) -> &String {
+            self.get(name)
+                .unwrap_or_else(|| panic!("no {} header", name))
+        }
+    }
+
+    // A response of any status; a 404 or 500 is not an error, so callers
+    // can still show the error page
+    #[derive(Debug)]
+    pub struct Response {
+        pub status: u16,
+        pub explanation: String,
+        pub headers: Headers,
+        pub body: Vec<u8>,
+    }
+
+    pub fn request(url: &str) -> Result<Response, RequestError> {
+        request_with_headers(url, &HashMap::new())
+    }
+
+    // Send body to url with the given content type
+    pub fn post(url: &str, content_type: &str, body: &[u8]) -> Result<Response, RequestError> {
+        HttpClient::new().post(url, content_type, body)
+    }
+
+    // Parse a "Name: Value" header line. The name must be an HTTP token,
+    // and neither part may contain a line break.
+    pub fn parse_header(line: &str) -> Option<(String, String)> {
+        let (name, value) = split2(line, ":")?;
+        let token = |ch: char| ch.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(ch);
+        if name.is_empty() || !name.chars().all(token) || value.contains(['\r', '\n']) {
+            return None;
+        }
+        Some((name.to_string(), value.trim().to_string()))
+    }
+
+    // Whether a method may be repeated without changing the outcome, as a
+    // second identical POST or PATCH might
+    pub fn is_idempotent(method: &str) -> bool {
+        matches!(
+            method.to_ascii_uppercase().as_str(),
+            "GET" | "HEAD" | "PUT" | "DELETE" | "OPTIONS" | "TRACE"
         )
-        .or(Err(RequestError::ConnectionError))?;
+    }
 
-        // 6. Receive response
-        let mut reader = BufReader::new(stream);
+    // Whether a failed request may be retried. One that failed before any
+    // of it was sent, such as a refused connection, never reached the
+    // server; one that failed after may have been processed already.
+    // RequestError::sent tells which a failure was.
+    pub fn retriable(method: &str, sent: bool) -> bool {
+        !sent || is_idempotent(method)
+    }
 
-        // 7. Read status line
-        let mut line = String::new();
-        reader
-            .read_line(&mut line)
-            .or(Err(RequestError::MalformedResponse))?;
+    // Like request, but waits at most timeout to connect and for each read
+    pub fn request_with_timeout(url: &str, timeout: Duration) -> Result<Response, RequestError> {
+        let mut client = HttpClient::new();
+        client.set_timeout(timeout);
+        client.get(url)
+    }
 
-        // 8. Parse status line
-        let (_version, status) = split2(&line, " ").ok_or(RequestError::MalformedResponse)?;
-        let (status, explanation) = split2(status, " ").ok_or(RequestError::MalformedResponse)?;
+    // Like request, but also sends extra. A header in extra replaces the
+    // default header of the same name, compared case-insensitively.
+    pub fn request_with_headers(
+        url: &str,
+        extra: &HashMap<String, String>,
+    ) -> Result<Response, RequestError> {
+        HttpClient::new().get_with_headers(url, extra)
+    }
 
-        // 9. Check status
-        match status {
-            "200" | "301" | "302" => (),
-            _ => {
-                return Err(RequestError::StatusError(
-                    status.to_string(),
-                    explanation.to_string(),
-                ))
-            }
+    // Like request, but calls progress as the body arrives with the number
+    // of bytes received so far and the Content-Length, if known.
+    pub fn request_with_progress(
+        url: &str,
+        progress: &mut dyn FnMut(usize, Option<usize>),
+    ) -> Result<Response, RequestError> {
+        HttpClient::new().get_with_progress(url, progress)
+    }
+
+    // Return the host of a URL, without port or credentials. Like request,
+    // a URL without a scheme is taken to start with the host.
+    fn host_of(url: &str) -> Option<&str> {
+        let url = split2(url, "://").map_or(url, |(_scheme, rest)| rest);
+        let authority = url.split(['/', '?', '#']).next()?;
+        let host = authority.rsplit('@').next()?;
+        host.split(':').next()
+    }
+
+    // Whether two URLs have the same scheme, host and port
+    fn same_origin(a: &str, b: &str) -> bool {
+        let origin = |url| {
+            let (scheme, rest) = split2(url, "://")?;
+            let authority = rest.split(['/', '?', '#']).next()?;
+            let host = authority.rsplit('@').next()?;
+            Some((scheme.to_ascii_lowercase(), host.to_ascii_lowercase()))
         };
+        origin(a).is_some() && origin(a) == origin(b)
+    }
 
-        // 10. Parse headers
-        let mut headers = HashMap::new();
-        loop {
-            line.clear();
-            reader
-                .read_line(&mut line)
-                .or(Err(RequestError::MalformedResponse))?;
-            if line == "\r\n" {
-                break;
-            }
-            let (header, value) = split2(&line, ":").ok_or(RequestError::MalformedResponse)?;
-            let header = header.to_ascii_lowercase();
-            let value = value.trim();
-            headers.insert(header, value.to_string());
+    #[derive(Debug, Clone)]
+    pub struct Cookie {
+        pub name: String,
+        pub value: String,
+        pub domain: String,
+        // Sent only to domain itself, not its subdomains, as when the
+        // cookie had no Domain attribute
+        pub host_only: bool,
+        pub path: String,
+        // None for a session cookie
+        pub expires: Option<SystemTime>,
+        // Sent only over https
+        pub secure: bool,
+    }
+
+    impl Cookie {
+        // Whether the cookie should be sent with a request for path on host,
+        // made over https if secure
+        fn matches(&self, secure: bool, host: &str, path: &str, now: SystemTime) -> bool {
+            let host = host.to_ascii_lowercase();
+            let domain_matches = host == self.domain
+                || (!self.host_only && host.ends_with(&format!(".{}", self.domain)));
+            let path_matches = path == self.path
+                || (path.starts_with(&self.path)
+                    && (self.path.ends_with('/') || path[self.path.len()..].starts_with('/')));
+            domain_matches
+                && path_matches
+                && (secure || !self.secure)
+                && self.expires.is_none_or(|expires| expires > now)
         }
+    }
 
-        if let Some(url) = headers.get("location") {
-            return request(url);
+    #[derive(Debug, Default)]
+    pub struct CookieJar {
+        cookies: Vec<Cookie>,
+    }
+
+    impl CookieJar {
+        pub fn new() -> CookieJar {
+            CookieJar::default()
         }
 
-        let content_encoding: ContentEncoding = match headers.get("content-encoding") {
-            Some(encoding) => encoding
-                .parse()
-                .or(Err(RequestError::UnsupportedEncoding))?,
-            None => ContentEncoding::Identity,
-        };
+        // Store a "name=value" cookie for the host of url, replacing any
+        // cookie of the same name. Returns false if either is malformed.
+        pub fn add(&mut self, url: &str, cookie: &str) -> bool {
+            let (host, (name, value)) = match (host_of(url), split2(cookie, "=")) {
+                (Some(host), Some(pair)) => (host, pair),
+                _ => return false,
+            };
+            let name = name.trim();
+            if name.is_empty() {
+                return false;
+            }
+            self.store(Cookie {
+                name: name.to_string(),
+                value: value.trim().to_string(),
+                domain: host.to_ascii_lowercase(),
+                host_only: true,
+                path: "/".to_string(),
+                expires: None,
+                secure: false,
+            });
+            true
+        }
 
-        // 11. Read body
-        // TODO(corona10): Implement ChunkedReader
-        let mut unchunked; // for chunked
-        let mut reader = match headers.get("transfer-encoding") {
-            Some(encoding) => {
-                unchunked = Vec::new();
-                if "chunked".eq_ignore_ascii_case(encoding) {
-                    loop {
-                        let mut line = String::new();
-                        reader
-                            .read_line(&mut line)
-                            .or(Err(RequestError::MalformedResponse))?;
-                        let n_bytes = u64::from_str_radix(line.trim_end(), 16).unwrap_or(0);
-                        if n_bytes == 0 {
-                            break;
+        // Store the cookie in a Set-Cookie header received from url. A
+        // cookie that has already expired removes the one it replaces.
+        // Returns false if the header is malformed, sets a cookie for
+        // another domain or for a whole top-level domain, or sets a Secure
+        // cookie over plain http.
+        pub fn set_cookie(&mut self, url: &str, header: &str) -> bool {
+            let host = match host_of(url) {
+                Some(host) => host.to_ascii_lowercase(),
+                None => return false,
+            };
+            let mut attributes = header.split(';');
+            let (name, value) = match attributes.next().and_then(|pair| split2(pair, "=")) {
+                Some((name, value)) if !name.trim().is_empty() => (name.trim(), value.trim()),
+                _ => return false,
+            };
+            let mut cookie = Cookie {
+                name: name.to_string(),
+                value: value.to_string(),
+                domain: host.clone(),
+                host_only: true,
+                path: default_cookie_path(url),
+                expires: None,
+                secure: false,
+            };
+            let mut max_age = None;
+            for attribute in attributes {
+                let (key, value) = split2(attribute, "=").unwrap_or((attribute, ""));
+                let (key, value) = (key.trim().to_ascii_lowercase(), value.trim());
+                match key.as_str() {
+                    "domain" if !value.is_empty() => {
+                        let domain = value.trim_start_matches('.').to_ascii_lowercase();
+                        if host != domain && !host.ends_with(&format!(".{}", domain)) {
+                            return false;
                         }
-                        let mut chunk = vec![0u8; n_bytes as usize];
-                        reader
-                            .read_exact(&mut chunk)
-                            .or(Err(RequestError::MalformedResponse))?;
-                        reader.read_exact(&mut vec![0u8; 2]).unwrap();
-                        unchunked.write_all(&chunk).unwrap();
+                        // A cookie for a single-label domain such as "com"
+                        // would go to every site under it, and one for an
+                        // IP address only to that address
+                        let ip = host.trim_start_matches('[').trim_end_matches(']');
+                        if !domain.contains('.') || ip.parse::<IpAddr>().is_ok() {
+                            if host != domain {
+                                return false;
+                            }
+                            continue;
+                        }
+                        cookie.domain = domain;
+                        cookie.host_only = false;
                     }
-                } else {
-                    unimplemented!()
+                    "path" if value.starts_with('/') => cookie.path = value.to_string(),
+                    "expires" => {
+                        if let Some(expires) = parse_http_date(value) {
+                            cookie.expires = Some(expires);
+                        }
+                    }
+                    "max-age" => max_age = value.parse::<i64>().ok().or(max_age),
+                    "secure" => cookie.secure = true,
+                    _ => (),
                 }
-                decompressor(BufReader::new(unchunked.as_slice()), content_encoding)
             }
-            None => decompressor(BufReader::new(reader), content_encoding),
-        };
-        let body = {
-            let mut body = Vec::new();
-            reader
-                .read_to_end(&mut body)
-                .or(Err(RequestError::MalformedResponse))?;
-            body
-        };
+            let scheme = split2(url, ":").map_or("", |(scheme, _)| scheme);
+            if cookie.secure && !scheme.eq_ignore_ascii_case("https") {
+                return false;
+            }
+            // Max-Age wins over Expires
+            if let Some(max_age) = max_age {
+                cookie.expires = Some(if max_age <= 0 {
+                    UNIX_EPOCH
+                } else {
+                    SystemTime::now() + Duration::from_secs(max_age as u64)
+                });
+            }
+            self.store(cookie);
+            true
+        }
+
+        // Add cookie, replacing any of the same name, domain and path
+        fn store(&mut self, cookie: Cookie) {
+            self.cookies.retain(|c| {
+                !(c.name == cookie.name && c.domain == cookie.domain && c.path == cookie.path)
+            });
+            if cookie
+                .expires
+                .is_none_or(|expires| expires > SystemTime::now())
+            {
+                self.cookies.push(cookie);
+            }
+        }
 
-        // In Rust, connection is closed when stream is dropped
+        // The Cookie header value to send with a request for path on host
+        // over scheme, if any unexpired cookies match
+        pub fn header(&self, scheme: &str, host: &str, path: &str) -> Option<String> {
+            let secure = scheme.eq_ignore_ascii_case("https");
+            let path = path.split(['?', '#']).next().unwrap_or(path);
+            let now = SystemTime::now();
+            let cookies: Vec<String> = self
+                .cookies
+                .iter()
+                .filter(|c| c.matches(secure, host, path, now))
+                .map(|c| format!("{}={}", c.name, c.value))
+                .collect();
+            if cookies.is_empty() {
+                None
+            } else {
+                Some(cookies.join("; "))
+            }
+        }
+    }
 
-        // 12. Return
-        Ok((headers, body))
+    // The path a cookie from url applies to when it names none: the
+    // directory of the path of url
+    fn default_cookie_path(url: &str) -> String {
+        let url = split2(url, "://").map_or(url, |(_scheme, rest)| rest);
+        let path = url.find('/').map_or("", |slash| &url[slash..]);
+        let path = path.split(['?', '#']).next().unwrap_or(path);
+        match path.rfind('/') {
+            Some(0) | None => "/".to_string(),
+            Some(slash) => path[..slash].to_string(),
+        }
     }
 
-    pub fn lex(body: &[u8]) -> String {
-        fn get_body(origin: &[u8]) -> &[u8] {
-            let body_re = Regex::new(r"<\s*body.*?>([\s\S]*)<\s*/body\s?>").unwrap();
-            match body_re.find(origin) {
-                Some(m) => &origin[m.start()..m.end()],
-                None => origin,
+    // Parse a date as sent in Expires, such as "Sun, 06 Nov 1994 08:49:37
+    // GMT". The fields are found by their form rather than their order, so
+    // that the dashed variant "Sunday, 06-Nov-94 08:49:37 GMT" parses too.
+    fn parse_http_date(date: &str) -> Option<SystemTime> {
+        const MONTHS: [&str; 12] = [
+            "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+        ];
+        let (mut day, mut month, mut year, mut time) = (None, None, None, None);
+        for token in date
+            .split([' ', ',', '-'])
+            .filter(|token| !token.is_empty())
+        {
+            let numeric = token.chars().all(|ch| ch.is_ascii_digit());
+            let lower = token.to_ascii_lowercase();
+            if time.is_none() && token.contains(':') {
+                let mut parts = token.split(':').map(|part| part.parse::<i64>().ok());
+                time = Some((parts.next()??, parts.next()??, parts.next()??));
+            } else if let Some(m) = MONTHS.iter().position(|m| lower.starts_with(m)) {
+                month = month.or(Some(m as i64 + 1));
+            } else if numeric && day.is_none() && token.len() <= 2 {
+                day = token.parse::<i64>().ok();
+            } else if numeric && year.is_none() {
+                year = token.parse::<i64>().ok().map(|year| match year {
+                    0..=69 => year + 2000,
+                    70..=99 => year + 1900,
+                    _ => year,
+                });
             }
         }
-        // 13. Print content
-        let mut in_angle = false;
-        let mut out: Vec<u8> = Vec::new();
-        let body = get_body(body);
-        for c in body {
-            match *c {
-                b'<' => in_angle = true,
-                b'>' => in_angle = false,
-                _ => {
-                    if !in_angle {
-                        out.push(*c);
-                    }
-                }
+        let (day, month, year, (hour, minute, second)) = (day?, month?, year?, time?);
+        if !(1..=31).contains(&day) || hour > 23 || minute > 59 || second > 60 {
+            return None;
+        }
+        // Days since the epoch, after Howard Hinnant's days_from_civil
+        let y = if month <= 2 { year - 1 } else { year };
+        let era = y.div_euclid(400);
+        let yoe = y.rem_euclid(400);
+        let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        let days = era * 146097 + doe - 719468;
+        let secs = days * 86400 + hour * 3600 + minute * 60 + second;
+        if secs < 0 {
+            return Some(UNIX_EPOCH);
+        }
+        Some(UNIX_EPOCH + Duration::from_secs(secs as u64))
+    }
+
+    // A request made by HttpClient, as recorded for a HAR log
+    #[derive(Debug, Clone)]
+    pub struct HarEntry {
+        pub started: SystemTime,
+        pub method: String,
+        pub url: String,
+        pub request_headers: Vec<(String, String)>,
+        pub request_body_size: usize,
+        pub status: u16,
+        pub status_text: String,
+        pub response_headers: Vec<(String, String)>,
+        pub response_body_size: usize,
+        pub connect: Duration,
+        pub send: Duration,
+        pub wait: Duration,
+        pub receive: Duration,
+    }
+
+    // Records every request made by an HttpClient, one entry per request
+    // including each redirect hop, and serializes them as HAR 1.2.
+    #[derive(Debug, Default)]
+    pub struct Har {
+        entries: Vec<HarEntry>,
+    }
+
+    fn json_string(s: &str) -> String {
+        let mut out = String::from("\"");
+        for c in s.chars() {
+            match c {
+                '"' => out.push_str("\\\""),
+                '\\' => out.push_str("\\\\"),
+                '\n' => out.push_str("\\n"),
+                '\r' => out.push_str("\\r"),
+                '\t' => out.push_str("\\t"),
+                c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+                c => out.push(c),
             }
         }
-        String::from_utf8(out).expect("utf-8 website is expected")
+        out.push('"');
+        out
     }
-}
 
-pub mod display {
-    use druid::piet::{FontFamily, Text, TextLayoutBuilder};
-    use druid::widget::prelude::*;
-    use druid::Color;
-    use std::cmp;
+    fn json_headers(headers: &[(String, String)]) -> String {
+        let headers: Vec<String> = headers
+            .iter()
+            .map(|(name, value)| {
+                format!(
+                    "{{\"name\":{},\"value\":{}}}",
+                    json_string(name),
+                    json_string(value)
+                )
+            })
+            .collect();
+        format!("[{}]", headers.join(","))
+    }
 
-    const WIDTH: i32 = 800;
-    const HEIGHT: i32 = 600;
-    const HSTEP: i32 = 13;
-    const VSTEP: i32 = 12;
-    const SCROLL_STEP: i32 = 100;
+    fn millis(duration: Duration) -> f64 {
+        duration.as_secs_f64() * 1000.0
+    }
 
-    struct Character {
-        x: i32,
-        y: i32,
-        ch: char,
+    // Format time as an ISO 8601 UTC timestamp with milliseconds
+    fn iso8601(time: SystemTime) -> String {
+        let since = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let secs = since.as_secs();
+        // Civil date from days since the epoch, after Howard Hinnant's
+        // days_from_civil inverse
+        let z = (secs / 86400) as i64 + 719468;
+        let era = z.div_euclid(146097);
+        let doe = z.rem_euclid(146097);
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+        format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+            year,
+            month,
+            day,
+            secs % 86400 / 3600,
+            secs % 3600 / 60,
+            secs % 60,
+            since.subsec_millis()
+        )
     }
 
-    pub struct BrowserWidget {
-        display_list: Vec<Character>,
-        scroll: i32,
-        min_scroll: i32,
-        max_scroll: i32,
+    impl Har {
+        pub fn new() -> Har {
+            Har::default()
+        }
+
+        pub fn entries(&self) -> &[HarEntry] {
+            &self.entries
+        }
+
+        pub fn to_json(&self) -> String {
+            let entries: Vec<String> = self
+                .entries
+                .iter()
+                .map(|entry| {
+                    let mime_type = entry
+                        .response_headers
+                        .iter()
+                        .find(|(name, _)| name.eq_ignore_ascii_case("content-type"))
+                        .map_or("", |(_, value)| value.as_str());
+                    let redirect_url = entry
+                        .response_headers
+                        .iter()
+                        .find(|(name, _)| name.eq_ignore_ascii_case("location"))
+                        .map_or("", |(_, value)| value.as_str());
+                    let time = entry.connect + entry.send + entry.wait + entry.receive;
+                    format!(
+                        "{{\"startedDateTime\":{},\"time\":{:.3},\
+                        \"request\":{{\"method\":{},\"url\":{},\"httpVersion\":\"HTTP/1.1\",\
+                        \"cookies\":[],\"headers\":{},\"queryString\":[],\
+                        \"headersSize\":-1,\"bodySize\":{}}},\
+                        \"response\":{{\"status\":{},\"statusText\":{},\"httpVersion\":\"HTTP/1.1\",\
+                        \"cookies\":[],\"headers\":{},\
+                        \"content\":{{\"size\":{},\"mimeType\":{}}},\
+                        \"redirectURL\":{},\"headersSize\":-1,\"bodySize\":-1}},\
+                        \"cache\":{{}},\
+                        \"timings\":{{\"connect\":{:.3},\"send\":{:.3},\"wait\":{:.3},\"receive\":{:.3}}}}}",
+                        json_string(&iso8601(entry.started)),
+                        millis(time),
+                        json_string(&entry.method),
+                        json_string(&entry.url),
+                        json_headers(&entry.request_headers),
+                        entry.request_body_size,
+                        entry.status,
+                        json_string(&entry.status_text),
+                        json_headers(&entry.response_headers),
+                        entry.response_body_size,
+                        json_string(mime_type),
+                        json_string(redirect_url),
+                        millis(entry.connect),
+                        millis(entry.send),
+                        millis(entry.wait),
+                        millis(entry.receive),
+                    )
+                })
+                .collect();
+            format!(
+                "{{\"log\":{{\"version\":\"1.2\",\
+                \"creator\":{{\"name\":{},\"version\":{}}},\
+                \"entries\":[{}]}}}}",
+                json_string(env!("CARGO_PKG_NAME")),
+                json_string(env!("CARGO_PKG_VERSION")),
+                entries.join(",")
+            )
+        }
     }
 
-    impl BrowserWidget {
-        pub fn new(text: String) -> BrowserWidget {
-            let mut cursor_x = HSTEP;
-            let mut cursor_y = VSTEP;
-            let mut max_scroll = 0;
-            let mut display_list = Vec::new();
-            for c in text.chars() {
-                max_scroll = cmp::max(max_scroll, cursor_y);
-                display_list.push(Character {
-                    x: cursor_x,
-                    y: cursor_y,
-                    ch: c,
-                });
-                cursor_x += VSTEP;
-                if cursor_x >= WIDTH - HSTEP || c == '\n' {
-                    cursor_y += VSTEP;
-                    cursor_x = HSTEP;
-                }
+    // An HTTP proxy to send requests through. Plain HTTP requests are
+    // forwarded by the proxy; HTTPS goes through a CONNECT tunnel.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct Proxy {
+        pub host: String,
+        pub port: u16,
+    }
+
+    impl Proxy {
+        // Parse a proxy URL such as "http://proxy.example:3128"
+        pub fn parse(url: &str) -> Result<Proxy, RequestError> {
+            let authority = match split2(url, "://") {
+                Some(("http", rest)) => rest,
+                Some((scheme, _)) => return Err(RequestError::UnknownScheme(scheme.to_string())),
+                None => url,
+            };
+            let authority = authority.strip_suffix('/').unwrap_or(authority);
+            let (host, port) = match split2(authority, ":") {
+                Some((host, port)) => (host, port.parse().or(Err(RequestError::MalformedUrl))?),
+                None => (authority, 80),
+            };
+            if host.is_empty() || host.contains('/') {
+                return Err(RequestError::MalformedUrl);
             }
-            BrowserWidget {
-                display_list,
-                scroll: 0,
-                min_scroll: 0,
-                max_scroll,
+            Ok(Proxy {
+                host: host.to_string(),
+                port,
+            })
+        }
+
+        // Ask the proxy to open a tunnel to host:port over stream
+        fn tunnel(
+            &self,
+            stream: &mut TcpStream,
+            host: &str,
+            port: u16,
+        ) -> Result<(), RequestError> {
+            write!(
+                stream,
+                "CONNECT {0}:{1} HTTP/1.1\r\n\
+                Host: {0}:{1}\r\n\
+                \r\n",
+                host, port
+            )
+            .map_err(|err| io_error(err, RequestError::ConnectionError))?;
+            // Read the reply a byte at a time so that nothing of the TLS
+            // stream that follows is consumed
+            let mut reply = Vec::new();
+            while !reply.ends_with(b"\r\n\r\n") {
+                let mut byte = [0u8];
+                stream
+                    .read_exact(&mut byte)
+                    .map_err(|err| io_error(err, RequestError::MalformedResponse))?;
+                reply.push(byte[0]);
+            }
+            let reply = String::from_utf8_lossy(&reply);
+            let status_line = reply.lines().next().unwrap_or("");
+            let (_version, status) =
+                split2(status_line, " ").ok_or(RequestError::MalformedResponse)?;
+            let (status, explanation) = split2(status, " ").unwrap_or((status, ""));
+            if status != "200" {
+                return Err(RequestError::StatusError(
+                    status.to_string(),
+                    explanation.to_string(),
+                ));
             }
+            Ok(())
         }
+    }
 
-        pub fn get_height() -> f64 {
-            HEIGHT as f64
+    #[derive(Debug, Default)]
+    pub struct HttpClient {
+        pub cookies: RefCell<CookieJar>,
+        pub proxy: Option<Proxy>,
+        // When set, every request is recorded here
+        pub har: Option<RefCell<Har>>,
+        // Replaces the default Accept-Encoding; see set_accept_encoding
+        accept_encoding: Option<String>,
+        // Return redirects as they are instead of following them
+        pub no_redirect: bool,
+        // Refuse redirects from https to http instead of only warning
+        pub strict_redirects: bool,
+        // DEFAULT_TIMEOUT unless set
+        timeout: Option<Duration>,
+        // Sent with every request, replacing any default of the same name
+        pub headers: Vec<(String, String)>,
+    }
+
+    impl HttpClient {
+        pub fn new() -> HttpClient {
+            HttpClient::default()
         }
 
-        pub fn get_width() -> f64 {
-            WIDTH as f64
+        // Wait at most timeout to connect, and for each read and write
+        pub fn set_timeout(&mut self, timeout: Duration) {
+            self.timeout = Some(timeout);
         }
-    }
 
-    impl Widget<i32> for BrowserWidget {
-        fn event(&mut self, ctx: &mut EventCtx, _event: &Event, _data: &mut i32, _env: &Env) {
-            match _event {
-                Event::Wheel(e) => {
-                    if e.wheel_delta.y < 0.0 {
-                        self.scroll -= SCROLL_STEP;
-                        self.scroll = cmp::max(self.scroll, self.min_scroll);
-                    } else if e.wheel_delta.y > 0.0 {
-                        self.scroll += SCROLL_STEP;
-                        self.scroll = cmp::min(self.scroll, self.max_scroll);
-                    }
-                    *_data = self.scroll;
-                    ctx.request_update();
+        // Send value as the Accept-Encoding header instead of the default.
+        // Every coding listed must be one decompress can handle.
+        pub fn set_accept_encoding(&mut self, value: &str) -> Result<(), RequestError> {
+            for coding in value.split(',') {
+                decodable(coding.split(';').next().unwrap_or(coding))?;
+            }
+            self.accept_encoding = Some(value.to_string());
+            Ok(())
+        }
+
+        // Whether a redirect from one URL to another may be followed. A
+        // redirect from https to http gives up encryption, so it is refused
+        // under strict_redirects and logged otherwise. A server may only
+        // redirect to http or https, never to a local file or the like.
+        pub fn check_redirect(&self, from: &str, to: &str) -> Result<(), RequestError> {
+            let scheme = |url| split2(url, ":").map_or("https", |(scheme, _)| scheme);
+            let web = |url| {
+                scheme(url).eq_ignore_ascii_case("http")
+                    || scheme(url).eq_ignore_ascii_case("https")
+            };
+            if web(from) && !web(to) {
+                return Err(RequestError::InsecureRedirect(to.to_string()));
+            }
+            if scheme(from).eq_ignore_ascii_case("https") && scheme(to).eq_ignore_ascii_case("http")
+            {
+                if self.strict_redirects {
+                    return Err(RequestError::InsecureRedirect(to.to_string()));
                 }
-                _ => {}
+                log::warn!("Following insecure redirect from {} to {}", from, to);
             }
+            Ok(())
         }
 
-        fn lifecycle(
-            &mut self,
-            _ctx: &mut LifeCycleCtx,
-            _event: &LifeCycle,
-            _data: &i32,
-            _env: &Env,
-        ) {
+        pub fn get(&self, url: &str) -> Result<Response, RequestError> {
+            let headers = self.extra_headers(&HashMap::new());
+            self.fetch("GET", url, &headers, None, &mut |_, _| (), 0)
         }
 
-        fn update(&mut self, ctx: &mut UpdateCtx, old_data: &i32, data: &i32, _env: &Env) {
-            if old_data != data {
-                ctx.request_paint();
+        pub fn get_with_progress(
+            &self,
+            url: &str,
+            progress: &mut dyn FnMut(usize, Option<usize>),
+        ) -> Result<Response, RequestError> {
+            let headers = self.extra_headers(&HashMap::new());
+            self.fetch("GET", url, &headers, None, progress, 0)
+        }
+
+        // Fetch url and parse the body as JSON, which its content type must
+        // say it is
+        #[cfg(feature = "json")]
+        pub fn get_json<T: serde::de::DeserializeOwned>(
+            &self,
+            url: &str,
+        ) -> Result<T, RequestError> {
+            let response = self.get(url)?;
+            let content_type = response
+                .headers
+                .get("content-type")
+                .map_or("", String::as_str);
+            let media_type = content_type.split(';').next().unwrap_or("").trim();
+            let media_type = media_type.to_ascii_lowercase();
+            if media_type != "application/json"
+                && media_type != "text/json"
+                && !media_type.ends_with("+json")
+            {
+                return Err(RequestError::NotJson(content_type.to_string()));
             }
+            serde_json::from_slice(&response.body)
+                .map_err(|err| RequestError::InvalidJson(err.to_string()))
         }
 
-        fn layout(
-            &mut self,
-            _layout_ctx: &mut LayoutCtx,
-            bc: &BoxConstraints,
-            _data: &i32,
-            _env: &Env,
-        ) -> Size {
-            bc.max()
+        // Fetch only the headers of url; the returned body is always empty.
+        pub fn head(&self, url: &str) -> Result<Response, RequestError> {
+            let headers = self.extra_headers(&HashMap::new());
+            self.fetch("HEAD", url, &headers, None, &mut |_, _| (), 0)
         }
 
-        fn paint(&mut self, ctx: &mut PaintCtx, _data: &i32, _env: &Env) {
-            let size = ctx.size();
-            let rect = size.to_rect();
-            ctx.fill(rect, &Color::WHITE);
-            for ch in &self.display_list {
-                if ch.y > self.scroll + HEIGHT {
-                    continue;
+        // Like get, but also sends the given request headers, which replace
+        // any default header of the same name
+        pub fn get_with_headers(
+            &self,
+            url: &str,
+            headers: &HashMap<String, String>,
+        ) -> Result<Response, RequestError> {
+            let headers = self.extra_headers(headers);
+            self.fetch("GET", url, &headers, None, &mut |_, _| (), 0)
+        }
+
+        // Send body to url with the given content type
+        pub fn post(
+            &self,
+            url: &str,
+            content_type: &str,
+            body: &[u8],
+        ) -> Result<Response, RequestError> {
+            let headers = self.extra_headers(&HashMap::new());
+            self.fetch(
+                "POST",
+                url,
+                &headers,
+                Some((content_type, body)),
+                &mut |_, _| (),
+                0,
+            )
+        }
+
+        // The headers to send besides the defaults: self.headers, then the
+        // headers given for this request
+        fn extra_headers(&self, headers: &HashMap<String, String>) -> Vec<(String, String)> {
+            let headers = headers
+                .iter()
+                .map(|(name, value)| (name.clone(), value.clone()));
+            self.headers.iter().cloned().chain(headers).collect()
+        }
+
+        // request_headers are sent in addition to the defaults, replacing
+        // any of the same name; payload is the content type and body to
+        // send, if any
+        fn fetch(
+            &self,
+            method: &str,
+            url: &str,
+            request_headers: &[(String, String)],
+            payload: Option<(&str, &[u8])>,
+            progress: &mut dyn FnMut(usize, Option<usize>),
+            redirects: usize,
+        ) -> Result<Response, RequestError> {
+            let base = url;
+
+            // 1. Parse scheme
+            let (scheme, url) = split2(url, ":").unwrap_or(("https", url));
+            let default_port = match scheme {
+                "http" => 80,
+                "https" => 443,
+                "data" => {
+                    // Exercise data scheme
+                    let (content_type, body) =
+                        split2(url, ",").ok_or(RequestError::MalformedUrl)?;
+                    let content_type = content_type.trim();
+                    // get, not indexing, as the media type may end in a
+                    // multibyte character
+                    let is_base64 = content_type.len() >= 7
+                        && content_type
+                            .get(content_type.len() - 7..)
+                            .is_some_and(|suffix| suffix.eq_ignore_ascii_case(";base64"));
+                    let (content_type, body) = if is_base64 {
+                        let mut encoded = percent_decode(body);
+                        encoded.retain(|byte| !byte.is_ascii_whitespace());
+                        let body = base64::decode(&encoded).or(Err(RequestError::MalformedUrl))?;
+                        (&content_type[..content_type.len() - 7], body)
+                    } else {
+                        (content_type, percent_decode(body))
+                    };
+                    // An omitted media type means text/plain, and an omitted
+                    // media type with no parameters means US-ASCII text
+                    let content_type = match content_type.trim() {
+                        "" => "text/plain;charset=US-ASCII".to_owned(),
+                        params if params.starts_with(';') => format!("text/plain{}", params),
+                        content_type => content_type.to_owned(),
+                    };
+                    let mut headers = Headers::new();
+                    headers.insert("content-type".to_owned(), content_type);
+                    return Ok(Response {
+                        status: 200,
+                        explanation: "OK".to_string(),
+                        headers,
+                        body,
+                    });
                 }
+                "view-source" => {
+                    // The inner URL is fetched as usual; page_text then
+                    // shows the markup instead of the text
+                    return self.fetch(method, url, request_headers, payload, progress, redirects);
+                }
+                "file" => {
+                    // file:///absolute/path, or file:relative/path for a path
+                    // relative to the working directory
+                    let path = url
+                        .strip_prefix("//")
+                        .map_or(url, |path| path.strip_prefix("localhost").unwrap_or(path));
+                    let path = path.split('#').next().unwrap_or(path);
+                    let path = String::from_utf8_lossy(&percent_decode(path)).into_owned();
+                    let body = fs::read(&path)
+                        .map_err(|err| RequestError::FileError(format!("{}: {}", path, err)))?;
+                    let extension = Path::new(&path).extension().and_then(|ext| ext.to_str());
+                    let content_type = match extension.map(str::to_ascii_lowercase).as_deref() {
+                        Some("html") | Some("htm") => "text/html",
+                        _ => "text/plain",
+                    };
+                    let mut headers = Headers::new();
+                    headers.insert("content-type".to_owned(), content_type.to_owned());
+                    return Ok(Response {
+                        status: 200,
+                        explanation: "OK".to_string(),
+                        headers,
+                        body,
+                    });
+                }
+                _ => return Err(RequestError::UnknownScheme(scheme.to_string())),
+            };
+            let url = url.strip_prefix("//").unwrap_or(url);
+            // Fragments are never sent to the server
+            let url = url.split('#').next().unwrap_or(url);
 
-                if ch.y + VSTEP < self.scroll {
-                    continue;
+            // 2. Parse host. A URL with no path, such as
+            // "https://example.com", asks for "/".
+            let (host, path) = url.split_at(url.find(['/', '?']).unwrap_or(url.len()));
+            let path = match path.strip_prefix('/') {
+                Some(_) => percent_encode_path(path),
+                None => percent_encode_path(&format!("/{}", path)),
+            };
+
+            // 3. Parse port
+            let (host, port) = match host.rfind(':') {
+                // A bracketed IPv6 address has colons of its own
+                Some(colon) if !host[colon..].contains(']') => {
+                    let port = host[colon + 1..]
+                        .parse()
+                        .or(Err(RequestError::MalformedUrl))?;
+                    (&host[..colon], port)
                 }
+                _ => (host, default_port),
+            };
+            let host =
+                String::from_utf8(percent_decode(host)).or(Err(RequestError::MalformedUrl))?;
+            if !valid_host(&host) {
+                return Err(RequestError::MalformedUrl);
+            }
+            let host = host.as_str();
+            let address = host.trim_start_matches('[').trim_end_matches(']');
 
-                let text = ctx.text();
-                let layout = text
-                    .new_text_layout(String::from(ch.ch))
-                    .font(FontFamily::default(), 12.0)
-                    .text_color(Color::BLACK)
-                    .build()
-                    .unwrap();
-                ctx.draw_text(&layout, (ch.x as f64, ch.y as f64 - self.scroll as f64));
+            // 4. Connect
+            let started = SystemTime::now();
+            let start = Instant::now();
+            // The timeouts also cover the TLS handshake, which runs over
+            // this stream
+            let timeout = self.timeout.unwrap_or(DEFAULT_TIMEOUT);
+            let stream = match &self.proxy {
+                Some(proxy) => {
+                    let mut stream = connect(&proxy.host, proxy.port, timeout)?;
+                    if scheme == "https" {
+                        proxy.tunnel(&mut stream, host, port)?;
+                    }
+                    stream
+                }
+                None => connect(address, port, timeout)?,
+            };
+            let mut stream = if scheme != "https" {
+                Stream::Tcp(stream)
+            } else {
+                let mut config = ClientConfig::new();
+                config
+                    .root_store
+                    .add_server_trust_anchors(&webpki_roots::TLS_SERVER_ROOTS);
+                let host =
+                    DNSNameRef::try_from_ascii_str(host).or(Err(RequestError::MalformedUrl))?;
+                let client = ClientSession::new(&Arc::new(config), host);
+                let stream = StreamOwned::new(client, stream);
+                Stream::Tls(Box::new(stream))
+            };
+            let connected = Instant::now();
+
+            // 5. Send request
+            let mut sent_headers = vec![
+                ("Host".to_string(), host.to_string()),
+                ("Connection".to_string(), "close".to_string()),
+                (
+                    "User-Agent".to_string(),
+                    format!("Mozilla/5.0 ({})", env::consts::OS),
+                ),
+                (
+                    "Accept-Encoding".to_string(),
+                    self.accept_encoding
+                        .clone()
+                        .unwrap_or_else(|| "gzip,deflate,br,zstd".to_string()),
+                ),
+            ];
+            if let Some(cookie) = self.cookies.borrow().header(scheme, host, &path) {
+                sent_headers.push(("Cookie".to_string(), cookie));
             }
-        }
-    }
-}
+            for (name, value) in request_headers {
+                sent_headers.retain(|(default, _)| !default.eq_ignore_ascii_case(name));
+                sent_headers.push((name.clone(), value.clone()));
+            }
+            if let Some((content_type, body)) = payload {
+                sent_headers.push(("Content-Type".to_string(), content_type.to_string()));
+                sent_headers.push(("Content-Length".to_string(), body.len().to_string()));
+            }
+            // Plain HTTP through a proxy uses the absolute-form request
+            // target; direct requests and HTTPS tunnels use the origin-form
+            let target = match &self.proxy {
+                Some(_) if scheme == "http" && port == 80 => format!("http://{}{}", host, path),
+                Some(_) if scheme == "http" => format!("http://{}:{}{}", host, port, path),
+                _ => path,
+            };
+            let mut head = format!("{} {} HTTP/1.1\r\n", method, target);
+            for (name, value) in &sent_headers {
+                head.push_str(&format!("{}: {}\r\n", name, value));
+            }
+            head.push_str("\r\n");
+            stream
+                .write_all(head.as_bytes())
+                .map_err(|err| io_error(err, RequestError::SendError))?;
+            if let Some((_content_type, body)) = payload {
+                stream
+                    .write_all(body)
+                    .map_err(|err| io_error(err, RequestError::SendError))?;
+            }
+            let sent = Instant::now();
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+            // 6. Receive response
+            let mut reader = BufReader::new(stream);
 
-    #[test]
-    fn test_http_request() -> Result<(), String> {
-        let http_sites = vec!["http://www.google.com/", "http://example.com/"];
-        for site in http_sites {
-            let (header, body) = http::request(site).unwrap();
-            assert_eq!(header.contains_key("content-type"), true);
-            assert_eq!(body.len() > 0, true);
-        }
-        Ok(())
-    }
+            // 7. Read status line
+            let mut line = String::new();
+            reader
+                .read_line(&mut line)
+                .map_err(|err| io_error(err, RequestError::MalformedResponse))?;
 
-    #[test]
-    fn test_https_request() -> Result<(), String> {
-        let https_sites = vec!["https://www.google.com/", "https://example.com/"];
-        for site in https_sites {
-            let (header, body) = http::request(site).unwrap();
-            assert_eq!(header.contains_key("content-type"), true);
-            assert_eq!(body.len() > 0, true);
-        }
-        Ok(())
-    }
+            let waited = Instant::now();
 
-    #[test]
-    fn test_data_request() -> Result<(), String> {
-        let (header, body) = http::request("data:text/html,Hello world").unwrap();
-        assert_eq!(header.get("content-type").unwrap(), "text/html");
+            // 8. Parse status line
+            let (_version, status) = split2(&line, " ").ok_or(RequestError::MalformedResponse)?;
+            let (status, explanation) =
+                split2(status, " ").ok_or(RequestError::MalformedResponse)?;
+            let status_code = status.parse().or(Err(RequestError::MalformedResponse))?;
+            let status_text = explanation.trim_end().to_string();
+
+            // 9. Parse headers
+            let mut headers = Headers::new();
+            loop {
+                line.clear();
+                reader
+                    .read_line(&mut line)
+                    .map_err(|err| io_error(err, RequestError::MalformedResponse))?;
+                if line == "\r\n" {
+                    break;
+                }
+                let (header, value) = split2(&line, ":").ok_or(RequestError::MalformedResponse)?;
+                let header = header.to_ascii_lowercase();
+                let value = value.trim();
+                headers.append(&header, value);
+            }
+            for set_cookie in headers.get_all("set-cookie") {
+                self.cookies.borrow_mut().set_cookie(base, set_cookie);
+            }
+
+            let record = |headers: &Headers, body_size: usize| {
+                if let Some(har) = &self.har {
+                    har.borrow_mut().entries.push(HarEntry {
+                        started,
+                        method: method.to_string(),
+                        url: base.to_string(),
+                        request_headers: sent_headers.clone(),
+                        request_body_size: payload.map_or(0, |(_, body)| body.len()),
+                        status: status_code,
+                        status_text: status_text.clone(),
+                        response_headers: headers
+                            .iter()
+                            .map(|(name, value)| (name.clone(), value.clone()))
+                            .collect(),
+                        response_body_size: body_size,
+                        connect: connected - start,
+                        send: sent - connected,
+                        wait: waited - sent,
+                        receive: waited.elapsed(),
+                    });
+                }
+            };
+
+            // Follow redirects, which may change the scheme, up to a limit.
+            // Other statuses may carry a Location too (201 names the created
+            // resource) but are left for the caller.
+            let redirect = !self.no_redirect && matches!(status_code, 301 | 302 | 303 | 307 | 308);
+            if let (true, Some(location)) = (redirect, headers.get("location")) {
+                record(&headers, 0);
+                if redirects == MAX_REDIRECTS {
+                    return Err(RequestError::TooManyRedirects);
+                }
+                let location = resolve(base, location);
+                self.check_redirect(base, &location)?;
+                // Credentials meant for one origin are not passed on to
+                // another, nor back again after
+                let same_origin = same_origin(base, &location);
+                let request_headers: Vec<(String, String)> = request_headers
+                    .iter()
+                    .filter(|(name, _)| {
+                        same_origin
+                            || !(name.eq_ignore_ascii_case("authorization")
+                                || name.eq_ignore_ascii_case("cookie"))
+                    })
+                    .cloned()
+                    .collect();
+                // 303 See Other is fetched with GET whatever the method was
+                let (method, payload) = if status_code == 303 && method != "HEAD" {
+                    ("GET", None)
+                } else {
+                    (method, payload)
+                };
+                return self.fetch(
+                    method,
+                    &location,
+                    &request_headers,
+                    payload,
+                    progress,
+                    redirects + 1,
+                );
+            }
+
+            // Responses to HEAD never have a body, and neither do 204 No
+            // Content and 304 Not Modified
+            if method == "HEAD" || status_code == 204 || status_code == 304 {
+                record(&headers, 0);
+                return Ok(Response {
+                    status: status_code,
+                    explanation: status_text,
+                    headers,
+                    body: Vec::new(),
+                });
+            }
+
+            let content_encoding = match headers.get("content-encoding") {
+                Some(encoding) => decodable(encoding)?,
+                None => ContentEncoding::Identity,
+            };
+
+            // Transfer-Encoding wins over Content-Length; a response with
+            // both may be an attempt at request smuggling
+            if headers.contains_key("transfer-encoding") {
+                if let Some(length) = headers.remove("content-length") {
+                    log::warn!(
+                        "Ignoring Content-Length: {} sent with Transfer-Encoding",
+                        length
+                    );
+                }
+            }
+
+            // Transfer codings are listed in the order applied, so chunked
+            // must come last; any before it are undone once it is
+            let transfer_codings = match headers.get("transfer-encoding") {
+                Some(value) => {
+                    let mut codings: Vec<&str> = value.split(',').map(str::trim).collect();
+                    if !codings
+                        .pop()
+                        .is_some_and(|last| "chunked".eq_ignore_ascii_case(last))
+                    {
+                        return Err(RequestError::UnsupportedEncoding);
+                    }
+                    let codings: Result<Vec<_>, _> = codings.into_iter().map(decodable).collect();
+                    Some(codings?)
+                }
+                None => None,
+            };
+
+            // 10. Read body
+            let total = headers
+                .get("content-length")
+                .and_then(|length| length.parse().ok());
+            let mut reader = BufReader::new(ProgressReader {
+                inner: reader,
+                received: 0,
+                total,
+                progress,
+            });
+            // TODO(corona10): Implement ChunkedReader
+            // Chunked and Content-Length bodies are read into memory first;
+            // otherwise the body runs until the server closes the connection
+            let raw = match (transfer_codings, total) {
+                (Some(codings), _) => {
+                    let mut unchunked = Vec::new();
+                    loop {
+                        let mut line = String::new();
+                        reader
+                            .read_line(&mut line)
+                            .map_err(|err| io_error(err, RequestError::MalformedResponse))?;
+                        // The chunk size may be followed by ;extensions
+                        let size = line.split(';').next().unwrap_or(&line).trim();
+                        let n_bytes = u64::from_str_radix(size, 16)
+                            .or(Err(RequestError::MalformedResponse))?;
+                        if n_bytes == 0 {
+                            break;
+                        }
+                        let mut chunk = vec![0u8; n_bytes as usize];
+                        reader
+                            .read_exact(&mut chunk)
+                            .map_err(|err| io_error(err, RequestError::MalformedResponse))?;
+                        reader
+                            .read_exact(&mut [0u8; 2])
+                            .map_err(|err| io_error(err, RequestError::MalformedResponse))?;
+                        unchunked.write_all(&chunk).unwrap();
+                    }
+                    for coding in codings.into_iter().rev() {
+                        if unchunked.is_empty() {
+                            break;
+                        }
+                        let mut decoded = Vec::new();
+                        decompress(unchunked.as_slice(), coding)
+                            .and_then(|mut reader| reader.read_to_end(&mut decoded))
+                            .map_err(|err| io_error(err, RequestError::MalformedResponse))?;
+                        unchunked = decoded;
+                    }
+                    Some(unchunked)
+                }
+                (None, Some(length)) => {
+                    // Stop after Content-Length bytes, so a server that keeps
+                    // the connection open does not hang the read
+                    let mut body = Vec::new();
+                    (&mut reader)
+                        .take(length as u64)
+                        .read_to_end(&mut body)
+                        .map_err(|err| io_error(err, RequestError::MalformedResponse))?;
+                    if body.len() < length {
+                        return Err(RequestError::MalformedResponse);
+                    }
+                    Some(body)
+                }
+                (None, None) => None,
+            };
+            let mut reader = match &raw {
+                // An empty body has nothing to decode, and GzDecoder would
+                // fail on the missing header
+                Some(raw) if raw.is_empty() => {
+                    decompress(raw.as_slice(), ContentEncoding::Identity)
+                }
+                Some(raw) => decompress(raw.as_slice(), content_encoding),
+                None => decompress(reader, content_encoding),
+            }
+            .map_err(|err| io_error(err, RequestError::MalformedResponse))?;
+            let body = {
+                let mut body = Vec::new();
+                reader
+                    .read_to_end(&mut body)
+                    .map_err(|err| io_error(err, RequestError::MalformedResponse))?;
+                body
+            };
+
+            // In Rust, connection is closed when stream is dropped
+
+            // 11. Return
+            record(&headers, body.len());
+            Ok(Response {
+                status: status_code,
+                explanation: status_text,
+                headers,
+                body,
+            })
+        }
+    }
+
+    // Connect to the first address of host that answers within timeout, and
+    // apply timeout to every read and write on the stream
+    fn connect(host: &str, port: u16, timeout: Duration) -> Result<TcpStream, RequestError> {
+        let addresses = (host, port)
+            .to_socket_addrs()
+            .or(Err(RequestError::ConnectionError))?;
+        let mut result = Err(RequestError::ConnectionError);
+        for address in addresses {
+            match TcpStream::connect_timeout(&address, timeout) {
+                Ok(stream) => {
+                    stream
+                        .set_read_timeout(Some(timeout))
+                        .and_then(|_| stream.set_write_timeout(Some(timeout)))
+                        .or(Err(RequestError::ConnectionError))?;
+                    return Ok(stream);
+                }
+                Err(err) => result = Err(io_error(err, RequestError::ConnectionError)),
+            }
+        }
+        result
+    }
+
+    // A read or write that ran out of time is a Timeout; any other failure
+    // is reported as otherwise
+    fn io_error(err: io::Error, otherwise: RequestError) -> RequestError {
+        match err.kind() {
+            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => RequestError::Timeout,
+            _ => otherwise,
+        }
+    }
+
+    // A host name of letters, digits, hyphens and dots, or a bracketed IPv6
+    // address
+    fn valid_host(host: &str) -> bool {
+        match host
+            .strip_prefix('[')
+            .and_then(|host| host.strip_suffix(']'))
+        {
+            Some(address) => address.parse::<Ipv6Addr>().is_ok(),
+            None => {
+                !host.is_empty()
+                    && host
+                        .chars()
+                        .all(|ch| ch.is_ascii_alphanumeric() || ch == '-' || ch == '.')
+            }
+        }
+    }
+
+    // Decode %XX escapes; malformed escapes are kept as they are
+    fn percent_decode(s: &str) -> Vec<u8> {
+        let bytes = s.as_bytes();
+        let mut out = Vec::with_capacity(bytes.len());
+        let mut i = 0;
+        while i < bytes.len() {
+            let hex = bytes.get(i + 1..i + 3).and_then(|hex| {
+                let hex = std::str::from_utf8(hex).ok()?;
+                u8::from_str_radix(hex, 16).ok()
+            });
+            match (bytes[i], hex) {
+                (b'%', Some(byte)) => {
+                    out.push(byte);
+                    i += 3;
+                }
+                (byte, _) => {
+                    out.push(byte);
+                    i += 1;
+                }
+            }
+        }
+        out
+    }
+
+    // Encode bytes that may not appear raw in a request target. Existing
+    // %XX escapes are left intact, so an encoded path is not encoded twice.
+    fn percent_encode_path(path: &str) -> String {
+        let bytes = path.as_bytes();
+        let mut out = String::with_capacity(bytes.len());
+        for (i, &byte) in bytes.iter().enumerate() {
+            let escape = byte == b'%'
+                && bytes
+                    .get(i + 1..i + 3)
+                    .is_some_and(|hex| hex.iter().all(u8::is_ascii_hexdigit));
+            if escape || (byte.is_ascii_graphic() && !b"%\"<>\\^`{|}".contains(&byte)) {
+                out.push(byte as char);
+            } else {
+                out.push_str(&format!("%{:02X}", byte));
+            }
+        }
+        out
+    }
+
+    // The charset parameter of a Content-Type value, if any
+    pub fn charset(content_type: &str) -> Option<&str> {
+        content_type
+            .split(';')
+            .skip(1)
+            .filter_map(|param| split2(param, "="))
+            .find(|(name, _)| "charset".eq_ignore_ascii_case(name.trim()))
+            .map(|(_, value)| value.trim().trim_matches('"'))
+    }
+
+    // Guess the MIME type of body from its first bytes. Anything that is not
+    // recognizably something else is rendered as HTML.
+    pub fn sniff(body: &[u8]) -> &'static str {
+        let start = body
+            .iter()
+            .position(|byte| !byte.is_ascii_whitespace())
+            .unwrap_or(body.len());
+        let body = &body[start..];
+        let signatures: [(&[u8], &str); 6] = [
+            (b"<?xml", "text/xml"),
+            (b"%PDF-", "application/pdf"),
+            (b"\x89PNG\r\n\x1a\n", "image/png"),
+            (b"GIF87a", "image/gif"),
+            (b"GIF89a", "image/gif"),
+            (b"\xff\xd8\xff", "image/jpeg"),
+        ];
+        signatures
+            .iter()
+            .find(|(signature, _)| body.starts_with(signature))
+            .map_or("text/html", |(_, mime_type)| mime_type)
+    }
+
+    // The Content-Type of a response, sniffed from the body when the header
+    // is missing or empty
+    pub fn content_type(headers: &Headers, body: &[u8]) -> String {
+        match headers.get("content-type").map(|value| value.trim()) {
+            Some(value) if !value.is_empty() => value.to_string(),
+            _ => sniff(body).to_string(),
+        }
+    }
+
+    fn decode_utf16(body: &[u8], from_bytes: fn([u8; 2]) -> u16) -> String {
+        let units = body
+            .chunks_exact(2)
+            .map(|pair| from_bytes([pair[0], pair[1]]));
+        char::decode_utf16(units)
+            .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+            .collect()
+    }
+
+    // What windows-1252 maps bytes 0x80 to 0x9F to. The rest of its bytes
+    // are the same as in Latin-1.
+    const WINDOWS_1252: [char; 32] = [
+        '\u{20ac}', '\u{81}', '\u{201a}', '\u{192}', '\u{201e}', '\u{2026}', '\u{2020}',
+        '\u{2021}', '\u{2c6}', '\u{2030}', '\u{160}', '\u{2039}', '\u{152}', '\u{8d}', '\u{17d}',
+        '\u{8f}', '\u{90}', '\u{2018}', '\u{2019}', '\u{201c}', '\u{201d}', '\u{2022}', '\u{2013}',
+        '\u{2014}', '\u{2dc}', '\u{2122}', '\u{161}', '\u{203a}', '\u{153}', '\u{9d}', '\u{17e}',
+        '\u{178}',
+    ];
+
+    // Decode body to text using the charset declared in content_type.
+    // A byte order mark takes precedence over the declared charset and is
+    // not part of the text. Like browsers, Latin-1 and ASCII labels are
+    // decoded as windows-1252. Anything other than those or UTF-16 is
+    // treated as UTF-8.
+    pub fn decode(body: &[u8], content_type: Option<&str>) -> String {
+        if let Some(body) = body.strip_prefix(b"\xef\xbb\xbf") {
+            return String::from_utf8_lossy(body).into_owned();
+        } else if let Some(body) = body.strip_prefix(b"\xff\xfe") {
+            return decode_utf16(body, u16::from_le_bytes);
+        } else if let Some(body) = body.strip_prefix(b"\xfe\xff") {
+            return decode_utf16(body, u16::from_be_bytes);
+        }
+        let charset = content_type.and_then(charset).unwrap_or("utf-8");
+        match charset.to_ascii_lowercase().as_str() {
+            "iso-8859-1" | "latin1" | "l1" | "us-ascii" | "ascii" | "windows-1252" | "cp1252" => {
+                body.iter()
+                    .map(|&byte| match byte {
+                        0x80..=0x9f => WINDOWS_1252[byte as usize - 0x80],
+                        _ => byte as char,
+                    })
+                    .collect()
+            }
+            // Like browsers, take unmarked "utf-16" to be little-endian
+            "utf-16" | "utf-16le" => decode_utf16(body, u16::from_le_bytes),
+            "utf-16be" => decode_utf16(body, u16::from_be_bytes),
+            _ => String::from_utf8_lossy(body).into_owned(),
+        }
+    }
+
+    fn has_scheme(url: &str) -> bool {
+        match split2(url, ":") {
+            Some((scheme, _)) => {
+                scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                    && scheme
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+            }
+            None => false,
+        }
+    }
+
+    // Collapse "." and ".." segments in an absolute path, leaving any query
+    // or fragment untouched.
+    fn remove_dot_segments(path: &str) -> String {
+        let end = path.find(['?', '#']).unwrap_or(path.len());
+        let (path, rest) = path.split_at(end);
+        let mut segments: Vec<&str> = Vec::new();
+        let mut parts = path.split('/').skip(1).peekable();
+        while let Some(segment) = parts.next() {
+            let last = parts.peek().is_none();
+            match segment {
+                "." | ".." => {
+                    if segment == ".." {
+                        segments.pop();
+                    }
+                    if last {
+                        segments.push("");
+                    }
+                }
+                _ => segments.push(segment),
+            }
+        }
+        format!("/{}{}", segments.join("/"), rest)
+    }
+
+    // Resolve url, such as a redirect's Location, against the URL it was
+    // found on. Handles absolute, scheme-relative ("//host/path"),
+    // root-relative ("/path"), query-only, fragment-only, and plain relative
+    // ("path") forms.
+    pub fn resolve(base: &str, url: &str) -> String {
+        if has_scheme(url) {
+            return url.to_string();
+        }
+        let (scheme, rest) = split2(base, ":").unwrap_or(("https", base));
+        if url.starts_with("//") {
+            return format!("{}:{}", scheme, url);
+        }
+        let rest = rest.strip_prefix("//").unwrap_or(rest);
+        let (host, path) = split2(rest, "/").unwrap_or((rest, ""));
+        let path = format!("/{}", path.split('#').next().unwrap_or(path));
+        let path = if url.starts_with('/') {
+            url.to_string()
+        } else if url.starts_with('#') {
+            format!("{}{}", path, url)
+        } else if url.starts_with('?') {
+            format!("{}{}", path.split('?').next().unwrap_or(&path), url)
+        } else {
+            let path = path.split('?').next().unwrap_or(&path);
+            let directory = &path[..path.rfind('/').map_or(0, |i| i + 1)];
+            format!("{}{}", directory, url)
+        };
+        format!("{}://{}{}", scheme, host, remove_dot_segments(&path))
+    }
+
+    // Parse a Content-Disposition header value. Returns the suggested
+    // filename if the response is an attachment to be saved rather than
+    // rendered. Directory components are dropped from the suggestion, and a
+    // hidden file name such as .bashrc is not suggested at all.
+    pub fn attachment_filename(value: &str) -> Option<String> {
+        let mut params = value.split(';').map(str::trim);
+        if !"attachment".eq_ignore_ascii_case(params.next()?) {
+            return None;
+        }
+        let filename = params
+            .filter_map(|param| split2(param, "="))
+            .find(|(name, _)| "filename".eq_ignore_ascii_case(name.trim()))
+            .map(|(_, value)| value.trim().trim_matches('"'))
+            .and_then(|value| value.rsplit(['/', '\\']).next())
+            .filter(|value| !value.is_empty() && !value.starts_with('.'));
+        Some(filename.unwrap_or("download").to_string())
+    }
+
+    // The plain text dump of a response to url: the text of the page, or
+    // for a view-source: URL the markup itself. Unlike in page_runs, the
+    // content of aria-hidden elements is left out, as it is decoration.
+    pub fn page_text(url: &str, headers: &Headers, body: &[u8]) -> String {
+        let text = decode(body, Some(&content_type(headers, body)));
+        if url.starts_with("view-source:") {
+            text
+        } else {
+            lex_runs(text.as_bytes(), true)
+                .into_iter()
+                .map(|(text, _)| text)
+                .collect()
+        }
+    }
+
+    // The text to show for a response to url, split into runs where the
+    // style changes
+    pub fn page_runs(url: &str, headers: &Headers, body: &[u8]) -> Vec<(String, Style)> {
+        let text = decode(body, Some(&content_type(headers, body)));
+        if url.starts_with("view-source:") {
+            vec![(text, Style::default())]
+        } else {
+            lex_styled(text.as_bytes())
+        }
+    }
+
+    // A file name to save url under: the last segment of its path without
+    // leading dots, or index.html when the path names a directory or the
+    // segment is not a name every system allows
+    pub fn url_filename(url: &str) -> String {
+        let url = url.split(['#', '?']).next().unwrap_or(url);
+        let rest = split2(url, "://").map_or(url, |(_scheme, rest)| rest);
+        let path = split2(rest, "/").map_or("", |(_host, path)| path);
+        let name = path.rsplit(['/', '\\']).next().unwrap_or("");
+        let name = name.trim_start_matches('.');
+        let unsafe_char = |c: char| c.is_control() || "<>:\"|?*".contains(c);
+        if name.is_empty() || name.contains(unsafe_char) {
+            "index.html".to_string()
+        } else {
+            name.to_string()
+        }
+    }
+
+    // A piece of markup: text between tags with character references
+    // decoded, or everything between a tag's < and >, such as "b", "/b" or
+    // "a href=\"/\"".
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum Token {
+        Text(String),
+        Tag(String),
+    }
+
+    // Split body into text and tags. Comments are dropped, and so is the
+    // content of script and style elements.
+    pub fn tokenize(body: &[u8]) -> Vec<Token> {
+        // Text between tags is copied a run at a time rather than byte by byte
+        let mut tokens = Vec::new();
+        let mut in_angle = false;
+        let mut rest = body;
+        while !rest.is_empty() {
+            let end = if in_angle {
+                rest.iter().position(|&c| c == b'>')
+            } else {
+                rest.iter().position(|&c| c == b'<' || c == b'>')
+            };
+            let end = match end {
+                Some(end) => end,
+                None => {
+                    if !in_angle {
+                        push_text(&mut tokens, rest);
+                    }
+                    break;
+                }
+            };
+            if in_angle {
+                let tag = String::from_utf8_lossy(&rest[..end]).into_owned();
+                // Script and style contents are not text, and may hold < and >
+                // of their own, so skip straight to the closing tag
+                let raw = raw_text_element(&tag);
+                tokens.push(Token::Tag(tag));
+                if let Some(name) = raw {
+                    rest = &rest[end + 1..];
+                    rest = &rest[closing_tag(rest, name).unwrap_or(rest.len())..];
+                    in_angle = false;
+                    continue;
+                }
+            } else {
+                push_text(&mut tokens, &rest[..end]);
+            }
+            // A comment may hold < and > of its own, so skip past the -->
+            // that ends it; an unterminated one runs to the end of the page.
+            // <!--> and <!---> are complete, empty comments.
+            if !in_angle && rest[end..].starts_with(b"<!--") {
+                let comment = &rest[end + 4..];
+                let len = if comment.starts_with(b">") {
+                    1
+                } else if comment.starts_with(b"->") {
+                    2
+                } else {
+                    comment
+                        .windows(3)
+                        .position(|end| end == b"-->")
+                        .map_or(comment.len(), |i| i + 3)
+                };
+                rest = &comment[len..];
+                continue;
+            }
+            in_angle = rest[end] == b'<';
+            rest = &rest[end + 1..];
+        }
+        tokens
+    }
+
+    // Decode text onto the end of tokens, joining it to the text before
+    // when nothing but a comment came between them
+    fn push_text(tokens: &mut Vec<Token>, text: &[u8]) {
+        if text.is_empty() {
+            return;
+        }
+        let mut out = Vec::with_capacity(text.len());
+        unescape(text, &mut out);
+        let text = String::from_utf8_lossy(&out).into_owned();
+        match tokens.last_mut() {
+            Some(Token::Text(last)) => last.push_str(&text),
+            _ => tokens.push(Token::Text(text)),
+        }
+    }
+
+    // How a run of text is drawn
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+    pub struct Style {
+        pub bold: bool,
+        pub italic: bool,
+    }
+
+    fn get_body(origin: &[u8]) -> &[u8] {
+        let body_re = Regex::new(r"<\s*body.*?>([\s\S]*)<\s*/body\s?>").unwrap();
+        match body_re.find(origin) {
+            Some(m) => &origin[m.start()..m.end()],
+            None => origin,
+        }
+    }
+
+    pub fn lex(body: &[u8]) -> String {
+        lex_styled(body).into_iter().map(|(text, _)| text).collect()
+    }
+
+    // The text of body as lex gives it, split into runs where the style
+    // changes. <b> and <strong> make text bold, and <i> and <em> italic,
+    // until they are closed or an element open around them is.
+    pub fn lex_styled(body: &[u8]) -> Vec<(String, Style)> {
+        lex_runs(body, false)
+    }
+
+    // lex_styled, but leaving out the content of aria-hidden elements when
+    // skip_hidden is set
+    fn lex_runs(body: &[u8], skip_hidden: bool) -> Vec<(String, Style)> {
+        // 12. Print content
+        let mut hidden = None;
+        // Names of the elements open, innermost last
+        let mut open: Vec<String> = Vec::new();
+        let mut runs: Vec<(String, Style)> = Vec::new();
+        for token in tokenize(get_body(body)) {
+            match token {
+                Token::Tag(tag) => {
+                    if skip_hidden {
+                        hidden = hide(hidden, &tag);
+                    }
+                    let (closing, name) = tag_name(&tag);
+                    if closing {
+                        // Closing an element closes whatever was left open
+                        // inside it too
+                        if let Some(i) = open.iter().rposition(|open| *open == name) {
+                            open.truncate(i);
+                        }
+                    } else if !name.is_empty()
+                        && !tag.trim_end().ends_with('/')
+                        && !VOID_ELEMENTS.contains(&name.as_str())
+                    {
+                        open.push(name);
+                    }
+                }
+                Token::Text(text) if hidden.is_none() => {
+                    let style = Style {
+                        bold: open.iter().any(|name| name == "b" || name == "strong"),
+                        italic: open.iter().any(|name| name == "i" || name == "em"),
+                    };
+                    match runs.last_mut() {
+                        Some((last, last_style)) if *last_style == style => last.push_str(&text),
+                        _ => runs.push((text, style)),
+                    }
+                }
+                Token::Text(_) => (),
+            }
+        }
+        runs
+    }
+
+    // Append text to out with character references such as &amp;, &#39;
+    // and &#x27; decoded. Anything that is not a complete reference is kept as it is.
+    fn unescape(text: &[u8], out: &mut Vec<u8>) {
+        let mut rest = text;
+        while let Some(amp) = rest.iter().position(|&c| c == b'&') {
+            out.extend_from_slice(&rest[..amp]);
+            rest = &rest[amp..];
+            match numeric_reference(rest).or_else(|| named_reference(rest)) {
+                Some((ch, len)) => {
+                    out.extend_from_slice(ch.encode_utf8(&mut [0; 4]).as_bytes());
+                    rest = &rest[len..];
+                }
+                None => {
+                    out.push(b'&');
+                    rest = &rest[1..];
+                }
+            }
+        }
+        out.extend_from_slice(rest);
+    }
+
+    // The character of the numeric reference text starts with, and the
+    // length of the reference. Code points that are not characters, like
+    // surrogates, become U+FFFD as in HTML.
+    fn numeric_reference(text: &[u8]) -> Option<(char, usize)> {
+        let digits = text.strip_prefix(b"&#")?;
+        let (radix, digits, prefix) = match digits.first() {
+            Some(b'x') | Some(b'X') => (16, &digits[1..], 3),
+            _ => (10, digits, 2),
+        };
+        let len = digits
+            .iter()
+            .take_while(|&&c| (c as char).is_digit(radix))
+            .count();
+        if len == 0 || digits.get(len) != Some(&b';') {
+            return None;
+        }
+        let digits = std::str::from_utf8(&digits[..len]).ok()?;
+        let ch = u32::from_str_radix(digits, radix)
+            .ok()
+            .filter(|&code| code != 0)
+            .and_then(char::from_u32)
+            .unwrap_or('\u{fffd}');
+        Some((ch, prefix + len + 1))
+    }
+
+    // Named character references lex decodes, without the & and ;. A
+    // non-breaking space is laid out as an ordinary one.
+    const NAMED_ENTITIES: &[(&str, char)] = &[
+        ("amp", '&'),
+        ("lt", '<'),
+        ("gt", '>'),
+        ("quot", '"'),
+        ("apos", '\''),
+        ("nbsp", ' '),
+        ("copy", '\u{a9}'),
+        ("reg", '\u{ae}'),
+        ("trade", '\u{2122}'),
+        ("hellip", '\u{2026}'),
+        ("ndash", '\u{2013}'),
+        ("mdash", '\u{2014}'),
+        ("lsquo", '\u{2018}'),
+        ("rsquo", '\u{2019}'),
+        ("ldquo", '\u{201c}'),
+        ("rdquo", '\u{201d}'),
+        ("laquo", '\u{ab}'),
+        ("raquo", '\u{bb}'),
+        ("bull", '\u{2022}'),
+        ("middot", '\u{b7}'),
+        ("deg", '\u{b0}'),
+        ("plusmn", '\u{b1}'),
+        ("times", '\u{d7}'),
+        ("divide", '\u{f7}'),
+        ("frac12", '\u{bd}'),
+        ("frac14", '\u{bc}'),
+        ("frac34", '\u{be}'),
+        ("sect", '\u{a7}'),
+        ("para", '\u{b6}'),
+        ("cent", '\u{a2}'),
+        ("pound", '\u{a3}'),
+        ("yen", '\u{a5}'),
+        ("euro", '\u{20ac}'),
+        ("iexcl", '\u{a1}'),
+        ("iquest", '\u{bf}'),
+        ("larr", '\u{2190}'),
+        ("uarr", '\u{2191}'),
+        ("rarr", '\u{2192}'),
+        ("darr", '\u{2193}'),
+        ("hearts", '\u{2665}'),
+    ];
+
+    // The character of the named reference text starts with, and the
+    // length of the reference
+    fn named_reference(text: &[u8]) -> Option<(char, usize)> {
+        let name = text.strip_prefix(b"&")?;
+        let len = name
+            .iter()
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .count();
+        if name.get(len) != Some(&b';') {
+            return None;
+        }
+        NAMED_ENTITIES
+            .iter()
+            .find(|(entity, _)| entity.as_bytes() == &name[..len])
+            .map(|&(_, ch)| (ch, len + 2))
+    }
+
+    // Elements that never have content, so never need a closing tag
+    const VOID_ELEMENTS: &[&str] = &[
+        "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source",
+        "track", "wbr",
+    ];
+
+    // Elements whose content is not markup and is never shown
+    const RAW_TEXT_ELEMENTS: &[&str] = &["script", "style"];
+
+    // Whether tag, the text between < and >, is a closing tag, and the
+    // lowercase name of the element
+    fn tag_name(tag: &str) -> (bool, String) {
+        let (closing, tag) = match tag.trim_start().strip_prefix('/') {
+            Some(tag) => (true, tag),
+            None => (false, tag.trim_start()),
+        };
+        let name = tag
+            .chars()
+            .take_while(|ch| ch.is_ascii_alphanumeric())
+            .collect::<String>()
+            .to_ascii_lowercase();
+        (closing, name)
+    }
+
+    // The raw text element tag opens, if any
+    fn raw_text_element(tag: &str) -> Option<&'static str> {
+        let (closing, name) = tag_name(tag);
+        if closing || tag.trim_end().ends_with('/') {
+            return None;
+        }
+        RAW_TEXT_ELEMENTS.iter().copied().find(|&raw| raw == name)
+    }
+
+    // Where the closing tag of the element name starts in text, matched
+    // case-insensitively
+    fn closing_tag(text: &[u8], name: &str) -> Option<usize> {
+        let len = name.len() + 2;
+        (0..text.len()).find(|&i| {
+            text[i..].starts_with(b"</")
+                && text
+                    .get(i + 2..i + len)
+                    .is_some_and(|tag| tag.eq_ignore_ascii_case(name.as_bytes()))
+                && !text.get(i + len).is_some_and(|c| c.is_ascii_alphanumeric())
+        })
+    }
+
+    // Whether the attributes of tag, the text between < and >, include
+    // aria-hidden="true"
+    fn aria_hidden(tag: &str) -> bool {
+        let tag = tag.to_ascii_lowercase();
+        tag.match_indices("aria-hidden").any(|(i, name)| {
+            let value = tag[i + name.len()..].trim_start();
+            match value.strip_prefix('=') {
+                Some(value) => value
+                    .trim_start()
+                    .trim_start_matches(['"', '\''])
+                    .starts_with("true"),
+                None => false,
+            }
+        })
+    }
+
+    // Track the aria-hidden element being skipped, if any, as tag goes by.
+    // The element is named with its nesting depth, since an element of the
+    // same name may be opened inside it before it is closed.
+    fn hide(hidden: Option<(String, usize)>, tag: &str) -> Option<(String, usize)> {
+        let (closing, name) = tag_name(tag);
+        let empty = tag.trim_end().ends_with('/') || VOID_ELEMENTS.contains(&name.as_str());
+        match hidden {
+            Some((hidden, depth)) if hidden == name && closing => {
+                if depth == 1 {
+                    None
+                } else {
+                    Some((hidden, depth - 1))
+                }
+            }
+            Some((hidden, depth)) if hidden == name && !empty => Some((hidden, depth + 1)),
+            Some(hidden) => Some(hidden),
+            None if !closing && !empty && !name.is_empty() && aria_hidden(tag) => Some((name, 1)),
+            None => None,
+        }
+    }
+}
+
+pub mod history {
+    use std::collections::HashMap;
+    use std::fs::{self, OpenOptions};
+    use std::io::{self, Write};
+    use std::path::{Path, PathBuf};
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct Visit {
+        pub url: String,
+        // Seconds since the Unix epoch
+        pub time: u64,
+    }
+
+    // Visited URLs, oldest first. When opened from a file, every visit is
+    // appended to it as a "time<TAB>url" line.
+    #[derive(Debug, Default)]
+    pub struct History {
+        path: Option<PathBuf>,
+        visits: Vec<Visit>,
+    }
+
+    impl History {
+        // An in-memory history that is not saved anywhere
+        pub fn new() -> History {
+            History::default()
+        }
+
+        // ~/.browser_history, if the home directory is known
+        pub fn default_path() -> Option<PathBuf> {
+            std::env::var_os("HOME").map(|home| Path::new(&home).join(".browser_history"))
+        }
+
+        // Load the history saved at path. A missing file is an empty
+        // history; malformed lines are skipped.
+        pub fn open(path: impl AsRef<Path>) -> io::Result<History> {
+            let path = path.as_ref();
+            let contents = match fs::read_to_string(path) {
+                Ok(contents) => contents,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+                Err(err) => return Err(err),
+            };
+            let visits = contents
+                .lines()
+                .filter_map(|line| {
+                    let mut fields = line.splitn(2, '\t');
+                    let time = fields.next()?.parse().ok()?;
+                    let url = fields.next()?.to_string();
+                    Some(Visit { url, time })
+                })
+                .collect();
+            Ok(History {
+                path: Some(path.to_path_buf()),
+                visits,
+            })
+        }
+
+        // Record a visit to url now in the history saved at path, without
+        // reading the visits already there
+        pub fn append(path: impl AsRef<Path>, url: &str) -> io::Result<()> {
+            let mut history = History {
+                path: Some(path.as_ref().to_path_buf()),
+                visits: Vec::new(),
+            };
+            history.add(url)
+        }
+
+        pub fn visits(&self) -> &[Visit] {
+            &self.visits
+        }
+
+        // Record a visit to url now
+        pub fn add(&mut self, url: &str) -> io::Result<()> {
+            let time = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_secs());
+            self.add_at(url, time)
+        }
+
+        // Record a visit to url at time, in seconds since the Unix epoch
+        pub fn add_at(&mut self, url: &str, time: u64) -> io::Result<()> {
+            // Tabs and newlines would break the file format
+            let url: String = url.chars().filter(|c| !c.is_control()).collect();
+            if let Some(path) = &self.path {
+                let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+                writeln!(file, "{}\t{}", time, url)?;
+            }
+            self.visits.push(Visit { url, time });
+            Ok(())
+        }
+
+        // Distinct visited URLs starting with prefix, most recent first.
+        // The prefix may leave out the scheme and "www."
+        pub fn query(&self, prefix: &str) -> Vec<&str> {
+            self.matching(prefix)
+                .into_iter()
+                .map(|(url, _, _)| url)
+                .collect()
+        }
+
+        // Up to limit autocomplete suggestions for prefix: matching URLs
+        // ranked by how often they were visited, then by how recently
+        pub fn suggest(&self, prefix: &str, limit: usize) -> Vec<&str> {
+            let mut ranked = self.matching(prefix);
+            ranked.sort_by(|a, b| b.1.cmp(&a.1).then(b.2.cmp(&a.2)));
+            ranked
+                .into_iter()
+                .take(limit)
+                .map(|(url, _, _)| url)
+                .collect()
+        }
+
+        // Distinct URLs matching prefix, most recent first, with how many
+        // times and when last each was visited
+        fn matching(&self, prefix: &str) -> Vec<(&str, usize, u64)> {
+            let prefix = lowercase_host(prefix);
+            let mut urls: Vec<(&str, usize, u64)> = Vec::new();
+            let mut index: HashMap<&str, usize> = HashMap::new();
+            for visit in self.visits.iter().rev() {
+                if !matches_prefix(&visit.url, &prefix) {
+                    continue;
+                }
+                match index.get(visit.url.as_str()) {
+                    Some(&i) => {
+                        let entry = &mut urls[i];
+                        entry.1 += 1;
+                        entry.2 = entry.2.max(visit.time);
+                    }
+                    None => {
+                        index.insert(visit.url.as_str(), urls.len());
+                        urls.push((&visit.url, 1, visit.time));
+                    }
+                }
+            }
+            urls
+        }
+    }
+
+    // s with its scheme and host in lowercase, as they are case-insensitive,
+    // and the rest left alone
+    fn lowercase_host(s: &str) -> String {
+        let host = s.find("://").map_or(0, |scheme| scheme + 3);
+        let end = s[host..]
+            .find(['/', '?', '#'])
+            .map_or(s.len(), |i| host + i);
+        format!("{}{}", s[..end].to_ascii_lowercase(), &s[end..])
+    }
+
+    // Whether url starts with prefix, which is already lowercased by
+    // lowercase_host, with or without its scheme and "www."
+    fn matches_prefix(url: &str, prefix: &str) -> bool {
+        let url = lowercase_host(url);
+        let without_scheme = url.split_once("://").map_or(&url[..], |(_, rest)| rest);
+        let without_www = without_scheme
+            .strip_prefix("www.")
+            .unwrap_or(without_scheme);
+        [&url[..], without_scheme, without_www]
+            .iter()
+            .any(|candidate| candidate.starts_with(prefix))
+    }
+}
+
+pub mod display {
+    use crate::history::History;
+    use crate::http::{self, page_runs, url_filename, HttpClient, RequestError, Style};
+    use druid::piet::{FontFamily, FontStyle, FontWeight, Text, TextLayout, TextLayoutBuilder};
+    use druid::widget::prelude::*;
+    use druid::{Color, KbKey, Rect};
+    use std::cmp;
+    use std::collections::HashMap;
+    use std::fs::{File, OpenOptions};
+    use std::io::{self, Write};
+    use std::path::PathBuf;
+
+    const WIDTH: i32 = 800;
+    const HEIGHT: i32 = 600;
+    const HSTEP: i32 = 13;
+    const VSTEP: i32 = 12;
+    const SCROLL_STEP: i32 = 100;
+    const SCROLL_MARGIN: i32 = 50;
+    const FONT_SIZE: f64 = 12.0;
+    // How many history suggestions the address bar lists
+    const SUGGESTIONS: usize = 8;
+
+    // Create a new file at path, or if one exists, at path with a number
+    // added to the file name, as in page-1.html. Returns the file and the
+    // path it was created at.
+    pub fn create_unique(path: &str) -> io::Result<(File, String)> {
+        let name = path.rfind(['/', '\\']).map_or(0, |slash| slash + 1);
+        let (stem, extension) = match path[name..].rfind('.') {
+            Some(dot) if dot > 0 => path.split_at(name + dot),
+            _ => (path, ""),
+        };
+        let mut candidate = path.to_string();
+        let mut n = 0;
+        loop {
+            match OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&candidate)
+            {
+                Ok(file) => return Ok((file, candidate)),
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                    n += 1;
+                    candidate = format!("{}-{}{}", stem, n, extension);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    // A word of the page, or the part of one in a single style, and where
+    // it is drawn
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct Word {
+        pub x: i32,
+        pub y: i32,
+        pub text: String,
+        pub style: Style,
+    }
+
+    // Set builder to the page font, in style
+    fn styled<B: TextLayoutBuilder>(builder: B, style: Style) -> B {
+        let builder = builder.font(FontFamily::default(), FONT_SIZE);
+        let builder = if style.bold {
+            builder.default_attribute(FontWeight::BOLD)
+        } else {
+            builder
+        };
+        if style.italic {
+            builder.default_attribute(FontStyle::Italic)
+        } else {
+            builder
+        }
+    }
+
+    // Default page colors, used wherever the page does not set its own
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct Theme {
+        pub background: Color,
+        pub foreground: Color,
+    }
+
+    impl Theme {
+        pub fn light() -> Theme {
+            Theme {
+                background: Color::WHITE,
+                foreground: Color::BLACK,
+            }
+        }
+
+        pub fn dark() -> Theme {
+            Theme {
+                background: Color::rgb8(0x1e, 0x1e, 0x1e),
+                foreground: Color::WHITE,
+            }
+        }
+    }
+
+    impl Default for Theme {
+        fn default() -> Theme {
+            Theme::light()
+        }
+    }
+
+    // CSS named colors, as 0xRRGGBB
+    const NAMED_COLORS: &[(&str, u32)] = &[
+        ("black", 0x000000),
+        ("silver", 0xc0c0c0),
+        ("gray", 0x808080),
+        ("grey", 0x808080),
+        ("white", 0xffffff),
+        ("maroon", 0x800000),
+        ("red", 0xff0000),
+        ("purple", 0x800080),
+        ("fuchsia", 0xff00ff),
+        ("magenta", 0xff00ff),
+        ("green", 0x008000),
+        ("lime", 0x00ff00),
+        ("olive", 0x808000),
+        ("yellow", 0xffff00),
+        ("navy", 0x000080),
+        ("blue", 0x0000ff),
+        ("teal", 0x008080),
+        ("aqua", 0x00ffff),
+        ("cyan", 0x00ffff),
+        ("orange", 0xffa500),
+    ];
+
+    // A number, or a percentage of full, clamped to 0..=full
+    fn color_component(s: &str, full: f64) -> Option<f64> {
+        let value = match s.strip_suffix('%') {
+            Some(percent) => percent.parse::<f64>().ok()? / 100.0 * full,
+            None => s.parse::<f64>().ok()?,
+        };
+        if value.is_nan() {
+            return None;
+        }
+        Some(value.max(0.0).min(full))
+    }
+
+    // Parse a CSS color: a name, #rgb, #rgba, #rrggbb, #rrggbbaa, or one of
+    // rgb(), rgba(), hsl() and hsla(). Out-of-range components are clamped.
+    pub fn parse_color(s: &str) -> Option<Color> {
+        let s = s.trim().to_ascii_lowercase();
+        if s == "transparent" {
+            return Some(Color::rgba8(0, 0, 0, 0));
+        }
+        if let Some(&(_, rgb)) = NAMED_COLORS.iter().find(|(name, _)| *name == s) {
+            return Some(Color::from_rgba32_u32((rgb << 8) | 0xff));
+        }
+        if let Some(hex) = s.strip_prefix('#') {
+            if !hex.chars().all(|ch| ch.is_ascii_hexdigit()) {
+                return None;
+            }
+            let digits: Vec<u32> = hex.chars().filter_map(|ch| ch.to_digit(16)).collect();
+            let rgba = match digits.len() {
+                3 | 4 => {
+                    let alpha = digits.get(3).copied().unwrap_or(0xf);
+                    digits[..3]
+                        .iter()
+                        .chain(&[alpha])
+                        .fold(0, |rgba, digit| (rgba << 8) | (digit * 0x11))
+                }
+                6 => (u32::from_str_radix(hex, 16).ok()? << 8) | 0xff,
+                8 => u32::from_str_radix(hex, 16).ok()?,
+                _ => return None,
+            };
+            return Some(Color::from_rgba32_u32(rgba));
+        }
+        let (function, args) = s.strip_suffix(')')?.split_once('(')?;
+        let args: Vec<&str> = args
+            .split(|ch: char| ch == ',' || ch == '/' || ch.is_whitespace())
+            .filter(|arg| !arg.is_empty())
+            .collect();
+        let alpha = match args.len() {
+            3 => 1.0,
+            4 => color_component(args[3], 1.0)?,
+            _ => return None,
+        };
+        match function.trim() {
+            "rgb" | "rgba" => {
+                let r = color_component(args[0], 255.0)?;
+                let g = color_component(args[1], 255.0)?;
+                let b = color_component(args[2], 255.0)?;
+                Some(Color::rgba(r / 255.0, g / 255.0, b / 255.0, alpha))
+            }
+            "hsl" | "hsla" => {
+                let h = args[0].strip_suffix("deg").unwrap_or(args[0]);
+                let h = h.parse::<f64>().ok()?.rem_euclid(360.0) / 360.0;
+                let s = color_component(args[1].strip_suffix('%')?, 100.0)? / 100.0;
+                let l = color_component(args[2].strip_suffix('%')?, 100.0)? / 100.0;
+                let q = if l < 0.5 {
+                    l * (1.0 + s)
+                } else {
+                    l + s - l * s
+                };
+                let p = 2.0 * l - q;
+                let hue = |t: f64| {
+                    let t = t.rem_euclid(1.0);
+                    if t < 1.0 / 6.0 {
+                        p + (q - p) * 6.0 * t
+                    } else if t < 0.5 {
+                        q
+                    } else if t < 2.0 / 3.0 {
+                        p + (q - p) * (2.0 / 3.0 - t) * 6.0
+                    } else {
+                        p
+                    }
+                };
+                Some(Color::rgba(
+                    hue(h + 1.0 / 3.0),
+                    hue(h),
+                    hue(h - 1.0 / 3.0),
+                    alpha,
+                ))
+            }
+            _ => None,
+        }
+    }
+
+    // The address typed after Ctrl+L, with suggestions for it from the
+    // history
+    #[derive(Debug, Default)]
+    struct AddressBar {
+        text: String,
+        history: History,
+        suggestions: Vec<String>,
+        // The suggestion picked with the arrow keys, shown in place of text
+        selected: Option<usize>,
+    }
+
+    impl AddressBar {
+        fn shown(&self) -> &str {
+            match self.selected {
+                Some(i) => &self.suggestions[i],
+                None => &self.text,
+            }
+        }
+
+        fn set_text(&mut self, text: String) {
+            self.suggestions = if text.is_empty() {
+                Vec::new()
+            } else {
+                self.history
+                    .suggest(&text, SUGGESTIONS)
+                    .into_iter()
+                    .map(str::to_string)
+                    .collect()
+            };
+            self.text = text;
+            self.selected = None;
+        }
+    }
+
+    pub struct BrowserWidget {
+        client: HttpClient,
+        // The page being shown, if it came from a URL that can be reloaded
+        url: Option<String>,
+        // The undecoded response body, for saving the page
+        body: Vec<u8>,
+        theme: Theme,
+        scroll_step: i32,
+        // Scroll the way the content moves rather than the way the view does
+        natural_scroll: bool,
+        // The runs of text laid out, and whether the widths of their words
+        // have been measured with the font yet
+        runs: Vec<(String, Style)>,
+        measured: bool,
+        display_list: Vec<Word>,
+        scroll: i32,
+        min_scroll: i32,
+        max_scroll: i32,
+        // The text being found after Ctrl+F, and the index in display_list
+        // of the word last found
+        find: Option<String>,
+        found: Option<usize>,
+        // Where visits are recorded and suggestions come from
+        history_path: Option<PathBuf>,
+        address: Option<AddressBar>,
+    }
+
+    // The width of word before there is a font to measure it with
+    fn estimate_width(word: &str, _style: Style) -> i32 {
+        word.chars().count() as i32 * VSTEP
+    }
+
+    // Words of runs, each made of its pieces in every style it has, with
+    // None where a newline comes
+    fn words(runs: &[(String, Style)]) -> Vec<Option<Vec<(&str, Style)>>> {
+        let mut words = Vec::new();
+        let mut word = Vec::new();
+        for (text, style) in runs {
+            for (i, line) in text.split('\n').enumerate() {
+                if i > 0 {
+                    if !word.is_empty() {
+                        words.push(Some(std::mem::take(&mut word)));
+                    }
+                    words.push(None);
+                }
+                for (j, piece) in line.split(char::is_whitespace).enumerate() {
+                    if j > 0 && !word.is_empty() {
+                        words.push(Some(std::mem::take(&mut word)));
+                    }
+                    if !piece.is_empty() {
+                        word.push((piece, *style));
+                    }
+                }
+            }
+        }
+        if !word.is_empty() {
+            words.push(Some(word));
+        }
+        words
+    }
+
+    // Lay out runs a word at a time, with measure giving the width of a
+    // word in a style. A word that would run past the right edge moves to
+    // the next line whole, unless no line could hold it, and a newline
+    // always starts one. Returns the display list and the largest scroll
+    // offset.
+    pub fn layout(
+        runs: &[(String, Style)],
+        mut measure: impl FnMut(&str, Style) -> i32,
+    ) -> (Vec<Word>, i32) {
+        let mut spaces = HashMap::new();
+        let mut cursor_x = HSTEP;
+        let mut cursor_y = VSTEP;
+        let mut max_scroll = 0;
+        let mut display_list = Vec::new();
+        for word in words(runs) {
+            let pieces = match word {
+                Some(pieces) => pieces,
+                None => {
+                    cursor_y += VSTEP;
+                    cursor_x = HSTEP;
+                    continue;
+                }
+            };
+            let widths: Vec<i32> = pieces
+                .iter()
+                .map(|&(piece, style)| measure(piece, style))
+                .collect();
+            let width: i32 = widths.iter().sum();
+            if cursor_x > HSTEP && cursor_x + width > WIDTH - HSTEP {
+                cursor_y += VSTEP;
+                cursor_x = HSTEP;
+            }
+            // A word too wide for any line, such as a long URL or a CJK
+            // sentence, breaks between characters instead
+            if width > WIDTH - 2 * HSTEP {
+                for &(piece, style) in &pieces {
+                    let (mut start, mut x) = (0, cursor_x);
+                    for (i, ch) in piece.char_indices() {
+                        let width = measure(&piece[i..i + ch.len_utf8()], style);
+                        if cursor_x > HSTEP && cursor_x + width > WIDTH - HSTEP {
+                            if i > start {
+                                display_list.push(Word {
+                                    x,
+                                    y: cursor_y,
+                                    text: piece[start..i].to_string(),
+                                    style,
+                                });
+                            }
+                            cursor_y += VSTEP;
+                            cursor_x = HSTEP;
+                            start = i;
+                            x = cursor_x;
+                        }
+                        cursor_x += width;
+                    }
+                    display_list.push(Word {
+                        x,
+                        y: cursor_y,
+                        text: piece[start..].to_string(),
+                        style,
+                    });
+                }
+                max_scroll = cmp::max(max_scroll, cursor_y);
+                let style = pieces[pieces.len() - 1].1;
+                cursor_x += *spaces.entry(style).or_insert_with(|| measure(" ", style));
+                continue;
+            }
+            max_scroll = cmp::max(max_scroll, cursor_y);
+            for (&(piece, style), width) in pieces.iter().zip(widths) {
+                display_list.push(Word {
+                    x: cursor_x,
+                    y: cursor_y,
+                    text: piece.to_string(),
+                    style,
+                });
+                cursor_x += width;
+            }
+            let style = pieces[pieces.len() - 1].1;
+            cursor_x += *spaces.entry(style).or_insert_with(|| measure(" ", style));
+        }
+        (display_list, max_scroll)
+    }
+
+    impl BrowserWidget {
+        pub fn new(text: String) -> BrowserWidget {
+            BrowserWidget::with_runs(vec![(text, Style::default())])
+        }
+
+        // Show runs of text in their styles
+        pub fn with_runs(runs: Vec<(String, Style)>) -> BrowserWidget {
+            let (display_list, max_scroll) = layout(&runs, estimate_width);
+            BrowserWidget {
+                client: HttpClient::new(),
+                url: None,
+                body: Vec::new(),
+                theme: Theme::default(),
+                scroll_step: SCROLL_STEP,
+                natural_scroll: false,
+                runs,
+                measured: false,
+                display_list,
+                scroll: 0,
+                min_scroll: 0,
+                max_scroll,
+                find: None,
+                found: None,
+                history_path: None,
+                address: None,
+            }
+        }
+
+        // Show runs of text fetched from url as body, so that the page can
+        // be reloaded and saved
+        pub fn with_url(
+            client: HttpClient,
+            url: &str,
+            body: Vec<u8>,
+            runs: Vec<(String, Style)>,
+        ) -> BrowserWidget {
+            BrowserWidget {
+                client,
+                url: Some(url.to_string()),
+                body,
+                ..BrowserWidget::with_runs(runs)
+            }
+        }
+
+        // Write the page as it was received to the current directory,
+        // named after its URL unless a file of that name already exists.
+        // Returns the file name.
+        pub fn save(&self) -> io::Result<String> {
+            let name = url_filename(self.url.as_deref().unwrap_or(""));
+            let (mut file, path) = create_unique(&name)?;
+            file.write_all(&self.body)?;
+            Ok(path)
+        }
+
+        pub fn set_theme(&mut self, theme: Theme) {
+            self.theme = theme;
+        }
+
+        pub fn theme(&self) -> &Theme {
+            &self.theme
+        }
+
+        pub fn set_scroll_step(&mut self, scroll_step: i32) {
+            self.scroll_step = scroll_step;
+        }
+
+        pub fn set_natural_scroll(&mut self, natural_scroll: bool) {
+            self.natural_scroll = natural_scroll;
+        }
+
+        pub fn set_history_path(&mut self, path: PathBuf) {
+            self.history_path = Some(path);
+        }
+
+        // Start typing an address, from the current URL. The history is
+        // read now, so that it includes visits made in other windows.
+        pub fn open_address_bar(&mut self) {
+            let history = match &self.history_path {
+                Some(path) => History::open(path).unwrap_or_else(|err| {
+                    log::error!("Could not read history from {}: {}", path.display(), err);
+                    History::new()
+                }),
+                None => History::new(),
+            };
+            let mut address = AddressBar {
+                history,
+                ..AddressBar::default()
+            };
+            address.set_text(self.url.clone().unwrap_or_default());
+            self.address = Some(address);
+            self.find = None;
+        }
+
+        // The address as shown, and the suggestions for it, while the
+        // address bar is open
+        pub fn address(&self) -> Option<(&str, &[String])> {
+            self.address
+                .as_ref()
+                .map(|address| (address.shown(), &address.suggestions[..]))
+        }
+
+        // Edit the address with key: type, erase, pick a suggestion with
+        // the arrow keys, or close with Escape. Returns the address to go to
+        // when Enter is pressed.
+        pub fn address_key(&mut self, key: &KbKey) -> Option<String> {
+            let address = self.address.as_mut()?;
+            match key {
+                KbKey::Character(c) => address.set_text(format!("{}{}", address.shown(), c)),
+                KbKey::Backspace => {
+                    let mut text = address.shown().to_string();
+                    text.pop();
+                    address.set_text(text);
+                }
+                KbKey::ArrowDown if !address.suggestions.is_empty() => {
+                    let last = address.suggestions.len() - 1;
+                    address.selected = Some(address.selected.map_or(0, |i| cmp::min(i + 1, last)));
+                }
+                KbKey::ArrowUp => {
+                    address.selected = address.selected.and_then(|i| i.checked_sub(1));
+                }
+                KbKey::Escape => self.address = None,
+                KbKey::Enter => {
+                    let url = address.shown().trim().to_string();
+                    self.address = None;
+                    return Some(url).filter(|url| !url.is_empty());
+                }
+                _ => {}
+            }
+            None
+        }
+
+        // Fetch url, show it from the top and record the visit
+        pub fn load(&mut self, url: &str) -> Result<(), RequestError> {
+            let http::Response { headers, body, .. } = self.client.get(url)?;
+            self.runs = page_runs(url, &headers, &body);
+            self.body = body;
+            self.url = Some(url.to_string());
+            self.scroll = 0;
+            self.found = None;
+            self.relayout(estimate_width);
+            self.measured = false;
+            if let Some(path) = &self.history_path {
+                if let Err(err) = History::append(path, url) {
+                    log::error!("Could not save history to {}: {}", path.display(), err);
+                }
+            }
+            Ok(())
+        }
+
+        // Scroll by one step for a wheel movement of delta_y, clamped to
+        // the page. Returns the new scroll offset.
+        pub fn wheel(&mut self, delta_y: f64) -> i32 {
+            let delta_y = if self.natural_scroll {
+                -delta_y
+            } else {
+                delta_y
+            };
+            if delta_y < 0.0 {
+                self.scroll -= self.scroll_step;
+            } else if delta_y > 0.0 {
+                self.scroll += self.scroll_step;
+            }
+            self.scroll = cmp::max(self.scroll, self.min_scroll);
+            self.scroll = cmp::min(self.scroll, self.max_scroll);
+            self.scroll
+        }
+
+        // Fetch the current URL again and lay it out. With bypass_cache,
+        // ask caches along the way to revalidate with the origin server.
+        pub fn reload(&mut self, bypass_cache: bool) -> Result<(), RequestError> {
+            let url = match &self.url {
+                Some(url) => url,
+                None => return Ok(()),
+            };
+            let mut headers = HashMap::new();
+            if bypass_cache {
+                headers.insert("Cache-Control".to_string(), "no-cache".to_string());
+            }
+            let http::Response { headers, body, .. } =
+                self.client.get_with_headers(url, &headers)?;
+            self.runs = page_runs(url, &headers, &body);
+            self.body = body;
+            self.relayout(estimate_width);
+            self.measured = false;
+            Ok(())
+        }
+
+        // Lay the text out again with widths from measure
+        fn relayout(&mut self, measure: impl FnMut(&str, Style) -> i32) {
+            let (display_list, max_scroll) = layout(&self.runs, measure);
+            self.display_list = display_list;
+            self.max_scroll = max_scroll;
+            self.scroll = cmp::min(self.scroll, self.max_scroll);
+        }
+
+        // Scroll so that rect (in page coordinates) lands comfortably inside
+        // the viewport: centered if it fits, otherwise its top is padded by
+        // SCROLL_MARGIN. Does nothing if rect is already comfortably visible.
+        // Returns the new scroll offset.
+        pub fn scroll_into_view(&mut self, rect: Rect) -> i32 {
+            let top = rect.y0 as i32;
+            let bottom = rect.y1 as i32;
+            let visible = top >= self.scroll + SCROLL_MARGIN
+                && bottom <= self.scroll + HEIGHT - SCROLL_MARGIN;
+            if !visible {
+                let height = bottom - top;
+                self.scroll = if height <= HEIGHT - 2 * SCROLL_MARGIN {
+                    top - (HEIGHT - height) / 2
+                } else {
+                    top - SCROLL_MARGIN
+                };
+                self.scroll = cmp::max(self.scroll, self.min_scroll);
+                self.scroll = cmp::min(self.scroll, self.max_scroll);
+            }
+            self.scroll
+        }
+
+        // Start finding text in the page
+        pub fn open_find(&mut self) {
+            self.find = Some(String::new());
+            self.found = None;
+            self.address = None;
+        }
+
+        // The text being found, while the find bar is open
+        pub fn find_text(&self) -> Option<&str> {
+            self.find.as_deref()
+        }
+
+        // Edit the text to find with key, or close the find bar with Escape.
+        // Enter goes to the next match, returning the new scroll offset.
+        pub fn find_key(&mut self, key: &KbKey) -> Option<i32> {
+            let text = self.find.as_mut()?;
+            match key {
+                KbKey::Character(c) => {
+                    text.push_str(c);
+                    self.found = None;
+                }
+                KbKey::Backspace => {
+                    text.pop();
+                    self.found = None;
+                }
+                KbKey::Escape => self.find = None,
+                KbKey::Enter => {
+                    let text = text.clone();
+                    return self.find_next(&text);
+                }
+                _ => {}
+            }
+            None
+        }
+
+        // Scroll to the next word after the one last found that contains
+        // text, ignoring case and starting over from the top after the last
+        // word. Returns the new scroll offset, or None if no word matches.
+        pub fn find_next(&mut self, text: &str) -> Option<i32> {
+            let text = text.to_lowercase();
+            let len = self.display_list.len();
+            if text.is_empty() || len == 0 {
+                return None;
+            }
+            let start = self.found.map_or(0, |i| i + 1);
+            let i = (start..start + len)
+                .map(|i| i % len)
+                .find(|&i| self.display_list[i].text.to_lowercase().contains(&text))?;
+            self.found = Some(i);
+            let word = &self.display_list[i];
+            let width = estimate_width(&word.text, word.style);
+            let rect = Rect::new(
+                word.x as f64,
+                word.y as f64,
+                (word.x + width) as f64,
+                (word.y + VSTEP) as f64,
+            );
+            Some(self.scroll_into_view(rect))
+        }
+
+        pub fn get_height() -> f64 {
+            HEIGHT as f64
+        }
+
+        pub fn get_width() -> f64 {
+            WIDTH as f64
+        }
+    }
+
+    impl Widget<i32> for BrowserWidget {
+        fn event(&mut self, ctx: &mut EventCtx, _event: &Event, _data: &mut i32, _env: &Env) {
+            match _event {
+                Event::WindowConnected => ctx.request_focus(),
+                Event::Wheel(e) => {
+                    *_data = self.wheel(e.wheel_delta.y);
+                    ctx.request_update();
+                }
+                Event::KeyDown(e) if self.address.is_some() => {
+                    if let Some(url) = self.address_key(&e.key) {
+                        if let Err(err) = self.load(&url) {
+                            log::error!("Could not load {}: {}", url, err);
+                        }
+                        *_data = self.scroll;
+                        ctx.request_layout();
+                    }
+                    ctx.request_paint();
+                }
+                Event::KeyDown(e) if self.find.is_some() => {
+                    if let Some(scroll) = self.find_key(&e.key) {
+                        *_data = scroll;
+                    }
+                    ctx.request_paint();
+                }
+                Event::KeyDown(e) => {
+                    let ctrl_l = match &e.key {
+                        KbKey::Character(c) => e.mods.ctrl() && c.eq_ignore_ascii_case("l"),
+                        _ => false,
+                    };
+                    if ctrl_l {
+                        self.open_address_bar();
+                        ctx.request_paint();
+                    }
+                    let ctrl_f = match &e.key {
+                        KbKey::Character(c) => e.mods.ctrl() && c.eq_ignore_ascii_case("f"),
+                        _ => false,
+                    };
+                    if ctrl_f {
+                        self.open_find();
+                        ctx.request_paint();
+                    }
+                    let ctrl_r = match &e.key {
+                        KbKey::Character(c) => e.mods.ctrl() && c.eq_ignore_ascii_case("r"),
+                        _ => false,
+                    };
+                    if e.key == KbKey::F5 || ctrl_r {
+                        if let Err(err) = self.reload(ctrl_r && e.mods.shift()) {
+                            log::error!("Reload failed: {}", err);
+                        }
+                        *_data = self.scroll;
+                        ctx.request_layout();
+                        ctx.request_paint();
+                    }
+                    let ctrl_s = match &e.key {
+                        KbKey::Character(c) => e.mods.ctrl() && c.eq_ignore_ascii_case("s"),
+                        _ => false,
+                    };
+                    if ctrl_s {
+                        match self.save() {
+                            Ok(path) => log::info!("Saved to {}", path),
+                            Err(err) => log::error!("Save failed: {}", err),
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        fn lifecycle(
+            &mut self,
+            _ctx: &mut LifeCycleCtx,
+            _event: &LifeCycle,
+            _data: &i32,
+            _env: &Env,
+        ) {
+        }
+
+        fn update(&mut self, ctx: &mut UpdateCtx, old_data: &i32, data: &i32, _env: &Env) {
+            if old_data != data {
+                ctx.request_paint();
+            }
+        }
+
+        fn layout(
+            &mut self,
+            layout_ctx: &mut LayoutCtx,
+            bc: &BoxConstraints,
+            _data: &i32,
+            _env: &Env,
+        ) -> Size {
+            // Words are first laid out with estimated widths, since measuring
+            // them needs the text factory only a context has
+            if !self.measured {
+                let text = layout_ctx.text();
+                self.relayout(|word, style| {
+                    styled(text.new_text_layout(word.to_string()), style)
+                        .build()
+                        .map_or_else(
+                            |_| estimate_width(word, style),
+                            |layout| layout.trailing_whitespace_width().ceil() as i32,
+                        )
+                });
+                self.measured = true;
+            }
+            bc.max()
+        }
+
+        fn paint(&mut self, ctx: &mut PaintCtx, _data: &i32, _env: &Env) {
+            let size = ctx.size();
+            let rect = size.to_rect();
+            ctx.fill(rect, &self.theme.background);
+            for word in &self.display_list {
+                if word.y > self.scroll + HEIGHT {
+                    continue;
+                }
+
+                if word.y + VSTEP < self.scroll {
+                    continue;
+                }
+
+                let text = ctx.text();
+                let layout = styled(text.new_text_layout(word.text.clone()), word.style)
+                    .text_color(self.theme.foreground.clone())
+                    .build()
+                    .unwrap();
+                ctx.draw_text(&layout, (word.x as f64, word.y as f64 - self.scroll as f64));
+            }
+            if let Some(text) = &self.find {
+                let bar = Rect::new(0.0, 0.0, size.width, (2 * VSTEP) as f64);
+                ctx.fill(bar, &self.theme.background);
+                ctx.stroke(bar, &self.theme.foreground, 1.0);
+                let layout = styled(
+                    ctx.text().new_text_layout(format!("Find: {}", text)),
+                    Style::default(),
+                )
+                .text_color(self.theme.foreground.clone())
+                .build()
+                .unwrap();
+                ctx.draw_text(&layout, (HSTEP as f64, 6.0));
+            }
+            // The address on the first line and suggestions below it, over
+            // the page, with the picked one in bold
+            if let Some(address) = &self.address {
+                let lines = 1 + address.suggestions.len();
+                let bar = Rect::new(0.0, 0.0, size.width, ((lines as i32 + 1) * VSTEP) as f64);
+                ctx.fill(bar, &self.theme.background);
+                ctx.stroke(bar, &self.theme.foreground, 1.0);
+                let suggestions = address.suggestions.iter().map(String::as_str);
+                for (line, text) in std::iter::once(address.shown())
+                    .chain(suggestions)
+                    .enumerate()
+                {
+                    let style = Style {
+                        bold: line > 0 && address.selected == Some(line - 1),
+                        ..Style::default()
+                    };
+                    let layout = styled(ctx.text().new_text_layout(text.to_string()), style)
+                        .text_color(self.theme.foreground.clone())
+                        .build()
+                        .unwrap();
+                    ctx.draw_text(&layout, (HSTEP as f64, (line as i32 * VSTEP) as f64 + 6.0));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    // Serve canned responses on a local port, one per connection. Returns
+    // the URL to fetch and a handle that yields the raw requests the server
+    // received.
+    fn serve(responses: Vec<Vec<u8>>) -> (String, thread::JoinHandle<Vec<Vec<u8>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            let mut requests = Vec::new();
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = Vec::new();
+                let mut buf = [0u8; 1024];
+                while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                    let len = stream.read(&mut buf).unwrap();
+                    if len == 0 {
+                        break;
+                    }
+                    request.extend_from_slice(&buf[..len]);
+                }
+                let head = String::from_utf8_lossy(&request).to_ascii_lowercase();
+                if let Some(length) = head.split("\r\ncontent-length: ").nth(1) {
+                    let length: usize = length.split("\r\n").next().unwrap().parse().unwrap();
+                    let head_len = head.find("\r\n\r\n").unwrap() + 4;
+                    while request.len() < head_len + length {
+                        let len = stream.read(&mut buf).unwrap();
+                        request.extend_from_slice(&buf[..len]);
+                    }
+                }
+                stream.write_all(&response).unwrap();
+                requests.push(request);
+            }
+            requests
+        });
+        (url, handle)
+    }
+
+    #[test]
+    fn test_http_request() -> Result<(), String> {
+        let http_sites = vec!["http://www.google.com/", "http://example.com/"];
+        for site in http_sites {
+            let http::Response { headers, body, .. } = http::request(site).unwrap();
+            assert!(headers.contains_key("content-type"));
+            assert!(!body.is_empty());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_https_request() -> Result<(), String> {
+        let https_sites = vec!["https://www.google.com/", "https://example.com/"];
+        for site in https_sites {
+            let http::Response { headers, body, .. } = http::request(site).unwrap();
+            assert!(headers.contains_key("content-type"));
+            assert!(!body.is_empty());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_data_request() -> Result<(), String> {
+        let http::Response { headers, body, .. } =
+            http::request("data:text/html,Hello world").unwrap();
+        assert_eq!(headers.get("content-type").unwrap(), "text/html");
         assert_eq!(std::str::from_utf8(&body).unwrap(), "Hello world");
         Ok(())
     }
 
+    #[test]
+    fn test_request_progress() -> Result<(), String> {
+        let mut response = b"HTTP/1.1 200 OK\r\nContent-Length: 100000\r\n\r\n".to_vec();
+        response.extend(vec![b'a'; 100000]);
+        let (url, server) = serve(vec![response]);
+        let mut received = Vec::new();
+        let http::Response { body, .. } = http::request_with_progress(&url, &mut |bytes, total| {
+            assert_eq!(total, Some(100000));
+            received.push(bytes);
+        })
+        .unwrap();
+        server.join().unwrap();
+        assert_eq!(body.len(), 100000);
+        assert!(received.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(received.last(), Some(&100000));
+        Ok(())
+    }
+
+    #[test]
+    fn test_base64_data_request() -> Result<(), String> {
+        let response = http::request("data:text/plain;base64,SGVsbG8=").unwrap();
+        assert_eq!(response.headers["content-type"], "text/plain");
+        assert_eq!(response.body, b"Hello");
+        let response = http::request("data:;BASE64,AP8%3D").unwrap();
+        assert_eq!(
+            response.headers["content-type"],
+            "text/plain;charset=US-ASCII"
+        );
+        assert_eq!(response.body, b"\0\xff");
+        let response = http::request("data:image/png;base64,iVBO Rw0K").unwrap();
+        assert_eq!(response.body, b"\x89PNG\r\n");
+        assert!(http::request("data:text/plain;base64,not base64!").is_err());
+        let response = http::request("data:€abcdef,x").unwrap();
+        assert_eq!(response.body, b"x");
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_request() -> Result<(), String> {
+        let path = std::env::temp_dir().join(format!("page-{}.HTML", std::process::id()));
+        std::fs::write(&path, "<p>Local</p>").unwrap();
+        let response = http::request(&format!("file://{}", path.display()));
+        std::fs::remove_file(&path).unwrap();
+        let response = response.unwrap();
+        assert_eq!(response.headers["content-type"], "text/html");
+        assert_eq!(response.body, b"<p>Local</p>");
+
+        let response = http::request("file:Cargo.toml").unwrap();
+        assert_eq!(response.headers["content-type"], "text/plain");
+        assert_eq!(response.body, std::fs::read("Cargo.toml").unwrap());
+
+        match http::request("file:///no/such/file.html") {
+            Err(http::RequestError::FileError(_)) => (),
+            _ => panic!("expected a missing file to be an error"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_view_source() -> Result<(), String> {
+        let page = [
+            &b"HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Encoding: gzip\r\n\r\n"[..],
+            &gzip(b"<body><p>Hi</p></body>"),
+        ]
+        .concat();
+        let (url, server) = serve(vec![page.clone(), page]);
+        let source = format!("view-source:{}", url);
+        let response = http::request(&source).unwrap();
+        assert_eq!(
+            http::page_text(&source, &response.headers, &response.body),
+            "<body><p>Hi</p></body>"
+        );
+        let response = http::request(&url).unwrap();
+        assert_eq!(
+            http::page_text(&url, &response.headers, &response.body),
+            "Hi"
+        );
+        server.join().unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn test_empty_data_request() -> Result<(), String> {
+        let http::Response { headers, body, .. } = http::request("data:,").unwrap();
+        assert_eq!(
+            headers.get("content-type").unwrap(),
+            "text/plain;charset=US-ASCII"
+        );
+        assert!(body.is_empty());
+        let http::Response { headers, body, .. } = http::request("data:text/plain,").unwrap();
+        assert_eq!(headers.get("content-type").unwrap(), "text/plain");
+        assert!(body.is_empty());
+        let http::Response { headers, .. } = http::request("data:;charset=utf-8,x").unwrap();
+        assert_eq!(
+            headers.get("content-type").unwrap(),
+            "text/plain;charset=utf-8"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_head_request() -> Result<(), String> {
+        let (url, server) = serve(vec![b"HTTP/1.1 200 OK\r\n\
+            Content-Type: text/html\r\n\
+            Content-Length: 5\r\n\
+            \r\n"
+            .to_vec()]);
+        let http::Response { headers, body, .. } = http::HttpClient::new().head(&url).unwrap();
+        let requests = server.join().unwrap();
+        assert!(requests[0].starts_with(b"HEAD / HTTP/1.1\r\n"));
+        assert_eq!(headers.get("content-type").unwrap(), "text/html");
+        assert!(body.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_seeded_cookie() -> Result<(), String> {
+        let (url, server) = serve(vec![b"HTTP/1.1 200 OK\r\n\r\n".to_vec()]);
+        let client = http::HttpClient::new();
+        assert!(client.cookies.borrow_mut().add(&url, "session=abc"));
+        assert!(client.cookies.borrow_mut().add(&url, "theme=dark"));
+        assert!(client
+            .cookies
+            .borrow_mut()
+            .add("http://example.com/", "other=1"));
+        assert!(!client.cookies.borrow_mut().add(&url, "malformed"));
+        client.get(&url).unwrap();
+        let request = String::from_utf8(server.join().unwrap().remove(0)).unwrap();
+        assert!(request.contains("\r\nCookie: session=abc; theme=dark\r\n"));
+        assert!(!request.contains("other=1"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_repeated_headers() -> Result<(), String> {
+        let (url, server) = serve(vec![b"HTTP/1.1 200 OK\r\n\
+            Warning: 110 - \"Response is Stale\"\r\n\
+            Content-Type: text/plain\r\n\
+            WARNING: 199 - \"Miscellaneous\"\r\n\r\n"
+            .to_vec()]);
+        let headers = http::request(&url).unwrap().headers;
+        server.join().unwrap();
+        assert_eq!(
+            headers.get_all("warning"),
+            vec!["110 - \"Response is Stale\"", "199 - \"Miscellaneous\""]
+        );
+        assert_eq!(headers["Warning"], "110 - \"Response is Stale\"");
+        assert_eq!(headers.get_all("content-type"), vec!["text/plain"]);
+        assert!(headers.get_all("location").is_empty());
+
+        let mut headers = http::Headers::new();
+        headers.append("Vary", "Accept");
+        headers.append("vary", "Cookie");
+        headers.insert("Vary".to_string(), "*".to_string());
+        assert_eq!(headers.get_all("vary"), vec!["*"]);
+        assert_eq!(headers.remove("VARY"), Some("*".to_string()));
+        assert!(!headers.contains_key("vary"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_cookie() -> Result<(), String> {
+        let (url, server) = serve(vec![
+            b"HTTP/1.1 302 Found\r\nLocation: /app/page\r\n\
+                Set-Cookie: id=1; Path=/; Max-Age=3600\r\n\
+                Set-Cookie: scoped=y; Path=/app; HttpOnly\r\n\
+                Set-Cookie: old=x; Expires=Thu, 01 Jan 1970 00:00:00 GMT\r\n\r\n"
+                .to_vec(),
+            b"HTTP/1.1 200 OK\r\nSet-Cookie: id=; Max-Age=0; Path=/\r\n\r\n".to_vec(),
+            b"HTTP/1.1 200 OK\r\n\r\n".to_vec(),
+        ]);
+        let client = http::HttpClient::new();
+        client.get(&url).unwrap();
+        client.get(&format!("{}other", url)).unwrap();
+        let requests: Vec<String> = server
+            .join()
+            .unwrap()
+            .into_iter()
+            .map(|request| String::from_utf8(request).unwrap())
+            .collect();
+        assert!(!requests[0].contains("Cookie:"));
+        assert!(requests[1].starts_with("GET /app/page HTTP/1.1\r\n"));
+        assert!(requests[1].contains("\r\nCookie: id=1; scoped=y\r\n"));
+        // id was deleted by Max-Age=0, and scoped does not cover /other
+        assert!(!requests[2].contains("Cookie:"));
+
+        let mut jar = http::CookieJar::new();
+        assert!(jar.set_cookie(
+            "http://www.example.com/a/b",
+            "wide=1; Domain=.Example.com; Path=/"
+        ));
+        assert!(jar.set_cookie("http://www.example.com/a/b", "narrow=2"));
+        assert!(jar.set_cookie(
+            "http://www.example.com/",
+            "later=3; Expires=Fri, 01-Jan-2100 00:00:00 GMT"
+        ));
+        assert!(!jar.set_cookie("http://www.example.com/", "evil=4; Domain=other.com"));
+        assert!(!jar.set_cookie("http://www.example.com/", "=5"));
+        assert_eq!(
+            jar.header("http", "sub.example.com", "/"),
+            Some("wide=1".to_string())
+        );
+        assert_eq!(
+            jar.header("http", "www.example.com", "/a/c?q"),
+            Some("wide=1; narrow=2; later=3".to_string())
+        );
+        assert_eq!(
+            jar.header("http", "www.example.com", "/ab"),
+            Some("wide=1; later=3".to_string())
+        );
+        assert_eq!(jar.header("http", "other.com", "/"), None);
+
+        let mut jar = http::CookieJar::new();
+        assert!(jar.set_cookie("https://www.example.com/", "sid=1; Secure"));
+        assert!(!jar.set_cookie("http://www.example.com/", "forged=1; Secure"));
+        assert_eq!(jar.header("http", "www.example.com", "/"), None);
+        assert_eq!(
+            jar.header("https", "www.example.com", "/"),
+            Some("sid=1".to_string())
+        );
+        assert!(!jar.set_cookie("http://www.example.com/", "tld=1; Domain=com"));
+        assert!(!jar.set_cookie("http://10.0.0.1/", "ip=1; Domain=0.0.1"));
+        assert!(jar.set_cookie("http://10.0.0.1/", "ip=2; Domain=10.0.0.1"));
+        assert!(jar.set_cookie("http://localhost/", "local=3; Domain=localhost"));
+        assert_eq!(jar.header("http", "evil.com", "/"), None);
+        assert_eq!(jar.header("http", "1.10.0.0.1", "/"), None);
+        assert_eq!(
+            jar.header("http", "10.0.0.1", "/"),
+            Some("ip=2".to_string())
+        );
+        assert_eq!(
+            jar.header("http", "localhost", "/"),
+            Some("local=3".to_string())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_post_request() -> Result<(), String> {
+        let (url, server) = serve(vec![b"HTTP/1.1 200 OK\r\n\r\nposted".to_vec()]);
+        let client = http::HttpClient::new();
+        let http::Response { body, .. } = client
+            .post(&url, "application/x-www-form-urlencoded", b"a=1&b=2")
+            .unwrap();
+        let request = String::from_utf8(server.join().unwrap().remove(0)).unwrap();
+        assert!(request.starts_with("POST / HTTP/1.1\r\n"));
+        assert!(request.contains("\r\nContent-Type: application/x-www-form-urlencoded\r\n"));
+        assert!(request.contains("\r\nContent-Length: 7\r\n"));
+        assert!(request.ends_with("\r\n\r\na=1&b=2"));
+        assert_eq!(body, b"posted");
+        Ok(())
+    }
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_gzip_trailer() -> Result<(), String> {
+        let head = b"HTTP/1.1 200 OK\r\nContent-Encoding: gzip\r\n\r\n".to_vec();
+        let (url, server) = serve(vec![[head.clone(), gzip(b"Hello world")].concat()]);
+        let http::Response { body, .. } = http::request(&url).unwrap();
+        server.join().unwrap();
+        assert_eq!(body, b"Hello world");
+
+        // Flip a bit of the CRC32 in the trailer
+        let mut tampered = gzip(b"Hello world");
+        let crc = tampered.len() - 8;
+        tampered[crc] ^= 1;
+        let (url, server) = serve(vec![[head, tampered].concat()]);
+        let result = http::request(&url);
+        server.join().unwrap();
+        assert!(matches!(result, Err(http::RequestError::MalformedResponse)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_data_request_charset() -> Result<(), String> {
+        let http::Response { headers, body, .. } =
+            http::request("data:text/plain;charset=iso-8859-1,caf%E9").unwrap();
+        assert_eq!(body, b"caf\xe9");
+        let content_type = headers.get("content-type").map(String::as_str);
+        assert_eq!(http::decode(&body, content_type), "caf\u{e9}");
+        let http::Response { headers, body, .. } =
+            http::request("data:text/plain;charset=utf-8,caf%C3%A9").unwrap();
+        let content_type = headers.get("content-type").map(String::as_str);
+        assert_eq!(http::decode(&body, content_type), "caf\u{e9}");
+        Ok(())
+    }
+
+    #[test]
+    fn test_har() -> Result<(), String> {
+        let (url, server) = serve(vec![
+            b"HTTP/1.1 302 Found\r\nLocation: /next\r\n\r\n".to_vec(),
+            b"HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\n\r\nDone".to_vec(),
+        ]);
+        let mut client = http::HttpClient::new();
+        client.har = Some(RefCell::new(http::Har::new()));
+        client.get(&url).unwrap();
+        server.join().unwrap();
+
+        let har = client.har.as_ref().unwrap().borrow();
+        let entries = har.entries();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].method, "GET");
+        assert_eq!(entries[0].url, url);
+        assert_eq!(entries[0].status, 302);
+        assert_eq!(entries[1].url, format!("{}next", url));
+        assert_eq!(entries[1].status, 200);
+        assert_eq!(entries[1].status_text, "OK");
+        assert_eq!(entries[1].response_body_size, 4);
+
+        let json = har.to_json();
+        assert!(json.starts_with("{\"log\":{\"version\":\"1.2\","));
+        assert_eq!(json.matches("\"startedDateTime\":").count(), 2);
+        assert!(json.contains("\"redirectURL\":\"/next\""));
+        assert!(json.contains("\"content\":{\"size\":4,\"mimeType\":\"text/plain\"}"));
+        assert!(json.contains("{\"name\":\"Host\",\"value\":\"127.0.0.1\"}"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_empty_content_type() -> Result<(), String> {
+        let (url, server) = serve(vec![
+            b"HTTP/1.1 200 OK\r\nContent-Type:\r\n\r\n\n<p>Hello</p>".to_vec(),
+            b"HTTP/1.1 200 OK\r\nContent-Type: \r\n\r\n\x89PNG\r\n\x1a\n".to_vec(),
+        ]);
+        let http::Response { headers, body, .. } = http::request(&url).unwrap();
+        assert_eq!(headers.get("content-type").unwrap(), "");
+        assert_eq!(http::content_type(&headers, &body), "text/html");
+        let http::Response { headers, body, .. } = http::request(&url).unwrap();
+        assert_eq!(http::content_type(&headers, &body), "image/png");
+        server.join().unwrap();
+
+        let mut headers = http::Headers::new();
+        headers.insert("content-type".to_string(), "text/plain".to_string());
+        assert_eq!(http::content_type(&headers, b"<p>"), "text/plain");
+        Ok(())
+    }
+
+    #[test]
+    fn test_brotli() -> Result<(), String> {
+        let original = b"Hello Brotli, hello Brotli, hello Brotli!".to_vec();
+        let mut compressed = Vec::new();
+        {
+            let mut writer = brotli::CompressorWriter::new(&mut compressed, 4096, 11, 22);
+            writer.write_all(&original).unwrap();
+        }
+        assert_ne!(compressed, original);
+        let mut reader = http::decompress(&compressed[..], http::ContentEncoding::Brotli).unwrap();
+        let mut body = Vec::new();
+        reader.read_to_end(&mut body).unwrap();
+        assert_eq!(body, original);
+
+        let response = [
+            &b"HTTP/1.1 200 OK\r\nContent-Encoding: br\r\n\r\n"[..],
+            &compressed,
+        ]
+        .concat();
+        let (url, server) = serve(vec![response]);
+        assert_eq!(http::request(&url).unwrap().body, original);
+        let requests = server.join().unwrap();
+        assert!(String::from_utf8_lossy(&requests[0])
+            .contains("Accept-Encoding: gzip,deflate,br,zstd\r\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_zstd() -> Result<(), String> {
+        let original = b"Hello Zstandard, hello Zstandard, hello Zstandard!".to_vec();
+        let compressed = zstd::encode_all(&original[..], 0).unwrap();
+        assert_ne!(compressed, original);
+        let mut reader = http::decompress(&compressed[..], http::ContentEncoding::Zstd).unwrap();
+        let mut body = Vec::new();
+        reader.read_to_end(&mut body).unwrap();
+        assert_eq!(body, original);
+
+        let response = [
+            &b"HTTP/1.1 200 OK\r\nContent-Encoding: zstd\r\n\r\n"[..],
+            &compressed,
+        ]
+        .concat();
+        let (url, server) = serve(vec![response]);
+        let mut client = http::HttpClient::new();
+        client.set_accept_encoding("zstd").unwrap();
+        assert_eq!(client.get(&url).unwrap().body, original);
+        let requests = server.join().unwrap();
+        assert!(String::from_utf8_lossy(&requests[0]).contains("\r\nAccept-Encoding: zstd\r\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_decompress_slice() -> Result<(), String> {
+        let compressed = gzip(b"Hello world");
+        let mut reader = http::decompress(&compressed[..], http::ContentEncoding::Gzip).unwrap();
+        let mut body = Vec::new();
+        reader.read_to_end(&mut body).unwrap();
+        assert_eq!(body, b"Hello world");
+
+        let mut reader = http::decompress(&b"plain"[..], http::ContentEncoding::Identity).unwrap();
+        let mut body = Vec::new();
+        reader.read_to_end(&mut body).unwrap();
+        assert_eq!(body, b"plain");
+        Ok(())
+    }
+
+    #[test]
+    fn test_proxy() -> Result<(), String> {
+        let (proxy_url, server) = serve(vec![b"HTTP/1.1 200 OK\r\n\r\nproxied".to_vec()]);
+        let mut client = http::HttpClient::new();
+        client.proxy = Some(http::Proxy::parse(&proxy_url).unwrap());
+        let http::Response { body, .. } = client.get("http://example.com/page").unwrap();
+        let requests = server.join().unwrap();
+        assert!(requests[0].starts_with(b"GET http://example.com/page HTTP/1.1\r\n"));
+        assert_eq!(body, b"proxied");
+
+        let proxy = http::Proxy::parse("proxy.example:3128").unwrap();
+        assert_eq!((proxy.host.as_str(), proxy.port), ("proxy.example", 3128));
+        assert!(http::Proxy::parse("ftp://proxy.example").is_err());
+        assert!(http::Proxy::parse("http://:3128").is_err());
+        assert!(http::Proxy::parse("http://proxy.example:port").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_request_target_form() -> Result<(), String> {
+        let ok = b"HTTP/1.1 200 OK\r\n\r\n".to_vec();
+        let (url, server) = serve(vec![ok.clone(), ok]);
+        let direct = http::HttpClient::new();
+        direct.get(&format!("{}a/b?c=d", url)).unwrap();
+        let mut proxied = http::HttpClient::new();
+        proxied.proxy = Some(http::Proxy::parse(&url).unwrap());
+        proxied.get("http://example.com:8080/a/b?c=d").unwrap();
+        let requests = server.join().unwrap();
+        assert!(requests[0].starts_with(b"GET /a/b?c=d HTTP/1.1\r\n"));
+        assert!(requests[1].starts_with(b"GET http://example.com:8080/a/b?c=d HTTP/1.1\r\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_percent_encoded_path() -> Result<(), String> {
+        let ok = b"HTTP/1.1 200 OK\r\n\r\n".to_vec();
+        let (url, server) = serve(vec![ok]);
+        http::HttpClient::new()
+            .get(&format!("{}a b/%41é?q=\"x\"", url))
+            .unwrap();
+        let requests = server.join().unwrap();
+        assert!(requests[0].starts_with(b"GET /a%20b/%41%C3%A9?q=%22x%22 HTTP/1.1\r\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_unsupported_content_encoding() -> Result<(), String> {
+        let compressed =
+            b"HTTP/1.1 200 OK\r\nContent-Encoding: compress\r\nContent-Length: 2\r\n\r\n\x1f\x9d"
+                .to_vec();
+        let (url, server) = serve(vec![compressed]);
+        match http::request(&url) {
+            Err(http::RequestError::UnsupportedEncoding) => (),
+            other => panic!("expected an unsupported encoding, got {:?}", other),
+        }
+        server.join().unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn test_accept_encoding() -> Result<(), String> {
+        let ok = b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nplain".to_vec();
+        let (url, server) = serve(vec![ok]);
+        let mut client = http::HttpClient::new();
+        assert!(client.set_accept_encoding("compress").is_err());
+        assert!(client.set_accept_encoding("gzip, xz").is_err());
+        client.set_accept_encoding("identity").unwrap();
+        let http::Response { headers, body, .. } = client.get(&url).unwrap();
+        assert!(!headers.contains_key("content-encoding"));
+        assert_eq!(body, b"plain");
+        let requests = server.join().unwrap();
+        let request = String::from_utf8_lossy(&requests[0]);
+        assert!(request.contains("\r\nAccept-Encoding: identity\r\n"));
+        assert!(!request.contains("gzip"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_empty_chunked_body() -> Result<(), String> {
+        let plain = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\n".to_vec();
+        let gzipped = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\
+            Content-Encoding: gzip\r\n\r\n0;ext=1\r\n\r\n"
+            .to_vec();
+        let (url, server) = serve(vec![plain, gzipped]);
+        let client = http::HttpClient::new();
+        assert_eq!(client.get(&url).unwrap().body, Vec::<u8>::new());
+        assert_eq!(client.get(&url).unwrap().body, Vec::<u8>::new());
+        server.join().unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn test_content_length_body() -> Result<(), String> {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let (done, finished) = std::sync::mpsc::channel();
+        let server = thread::spawn(move || {
+            let responses: [&[u8]; 3] = [
+                // The connection stays open after the body
+                b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nHello",
+                b"HTTP/1.1 204 No Content\r\nContent-Length: 3\r\n\r\n",
+                b"HTTP/1.1 200 OK\r\nContent-Length: 9\r\n\r\nShort",
+            ];
+            let mut streams = Vec::new();
+            for (i, response) in responses.iter().enumerate() {
+                let (mut stream, _) = listener.accept().unwrap();
+                stream.write_all(response).unwrap();
+                if i == 2 {
+                    drop(stream);
+                } else {
+                    streams.push(stream);
+                }
+            }
+            finished.recv().unwrap();
+        });
+        let client = http::HttpClient::new();
+        assert_eq!(client.get(&url).unwrap().body, b"Hello");
+        assert!(client.get(&url).unwrap().body.is_empty());
+        match client.get(&url) {
+            Err(http::RequestError::MalformedResponse) => (),
+            _ => panic!("expected a short body to be malformed"),
+        }
+        done.send(()).unwrap();
+        server.join().unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn test_request_with_headers() -> Result<(), String> {
+        let ok = b"HTTP/1.1 200 OK\r\n\r\n".to_vec();
+        let (url, server) = serve(vec![ok]);
+        let mut extra = HashMap::new();
+        extra.insert("Accept".to_string(), "text/plain".to_string());
+        extra.insert("user-agent".to_string(), "test-agent/1.0".to_string());
+        extra.insert("ACCEPT-ENCODING".to_string(), "identity".to_string());
+        http::request_with_headers(&url, &extra).unwrap();
+        let requests = server.join().unwrap();
+        let request = String::from_utf8_lossy(&requests[0]);
+        assert!(request.contains("\r\nAccept: text/plain\r\n"));
+        assert!(request.contains("\r\nuser-agent: test-agent/1.0\r\n"));
+        assert!(request.contains("\r\nACCEPT-ENCODING: identity\r\n"));
+        assert!(!request.contains("Mozilla"));
+        assert!(!request.contains("gzip"));
+        assert!(request.contains("\r\nHost: 127.0.0.1\r\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_redirect_credentials() -> Result<(), String> {
+        let (other, other_server) = serve(vec![b"HTTP/1.1 200 OK\r\n\r\n".to_vec()]);
+        let (url, server) = serve(vec![
+            b"HTTP/1.1 302 Found\r\nLocation: /next\r\n\r\n".to_vec(),
+            format!("HTTP/1.1 302 Found\r\nLocation: {}\r\n\r\n", other).into_bytes(),
+        ]);
+        let mut client = http::HttpClient::new();
+        client
+            .headers
+            .push(("Cookie".to_string(), "sid=1".to_string()));
+        let mut extra = HashMap::new();
+        extra.insert("Authorization".to_string(), "Bearer secret".to_string());
+        extra.insert("X-Debug".to_string(), "on".to_string());
+        client.get_with_headers(&url, &extra).unwrap();
+        let requests = server.join().unwrap();
+        let redirected = String::from_utf8(other_server.join().unwrap().remove(0)).unwrap();
+        // Kept on the same origin, dropped on the other
+        for request in &requests {
+            let request = String::from_utf8_lossy(request);
+            assert!(request.contains("\r\nAuthorization: Bearer secret\r\n"));
+            assert!(request.contains("\r\nCookie: sid=1\r\n"));
+        }
+        assert!(!redirected.contains("Authorization"));
+        assert!(!redirected.contains("Cookie"));
+        assert!(redirected.contains("\r\nX-Debug: on\r\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_host_validation() -> Result<(), String> {
+        for url in &[
+            "http://exa mple.com/",
+            "http://bad_host!/",
+            "http://:8080/",
+            "http://[::g]/",
+            "http://example.com:port/",
+        ] {
+            match http::request(url) {
+                Err(http::RequestError::MalformedUrl) => (),
+                other => panic!("expected {} to be malformed, got {:?}", url, other),
+            }
+        }
+        let ok = b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok".to_vec();
+        let (url, server) = serve(vec![ok]);
+        let encoded = url.replace("127.0.0.1", "127.0.0.%31");
+        assert_eq!(http::request(&encoded).unwrap().body, b"ok");
+        server.join().unwrap();
+        if let Ok(listener) = TcpListener::bind("[::1]:0") {
+            let port = listener.local_addr().unwrap().port();
+            let server = thread::spawn(move || {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = [0u8; 1024];
+                let n = stream.read(&mut request).unwrap();
+                stream.write_all(b"HTTP/1.1 200 OK\r\n\r\nv6").unwrap();
+                request[..n].to_vec()
+            });
+            let response = http::request(&format!("http://[::1]:{}/", port)).unwrap();
+            assert_eq!(response.body, b"v6");
+            let request = server.join().unwrap();
+            assert!(String::from_utf8_lossy(&request).contains("\r\nHost: [::1]\r\n"));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_retriable() -> Result<(), String> {
+        for method in &["GET", "HEAD", "PUT", "DELETE", "post", "PATCH"] {
+            assert!(http::retriable(method, false), "{} before sending", method);
+        }
+        for method in &["GET", "head", "PUT", "DELETE", "OPTIONS"] {
+            assert!(http::retriable(method, true), "{} after sending", method);
+        }
+        for method in &["POST", "patch", "CONNECT"] {
+            assert!(!http::retriable(method, true), "{} after sending", method);
+        }
+
+        // Nothing listens on a port just freed, so the connection is refused
+        // before anything is sent
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let url = format!("http://127.0.0.1:{}/", port);
+        let err = http::post(&url, "text/plain", b"x").unwrap_err();
+        assert!(!err.sent(), "{:?}", err);
+        assert!(http::retriable("POST", err.sent()));
+
+        // A server that reads the request and hangs up has it, and might have
+        // acted on it
+        let (url, server) = serve(vec![Vec::new()]);
+        let err = http::post(&url, "text/plain", b"x").unwrap_err();
+        server.join().unwrap();
+        assert!(err.sent(), "{:?}", err);
+        assert!(!http::retriable("POST", err.sent()));
+        assert!(http::retriable("PUT", err.sent()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_timeout() -> Result<(), String> {
+        // Accept connections but never answer
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let streams: Vec<_> = listener.incoming().take(2).collect();
+            thread::sleep(std::time::Duration::from_millis(500));
+            drop(streams);
+        });
+        let timeout = std::time::Duration::from_millis(100);
+        let url = format!("http://127.0.0.1:{}/", port);
+        match http::request_with_timeout(&url, timeout) {
+            Err(err @ http::RequestError::Timeout) => {
+                assert_eq!(err.to_string(), "Connection timed out")
+            }
+            other => panic!("expected a timeout, got {:?}", other),
+        }
+        // The TLS handshake waits for a ServerHello that never comes
+        let url = format!("https://localhost:{}/", port);
+        match http::request_with_timeout(&url, timeout) {
+            Err(http::RequestError::Timeout) => (),
+            other => panic!("expected the handshake to time out, got {:?}", other),
+        }
+        server.join().unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn test_client_headers() -> Result<(), String> {
+        assert_eq!(
+            http::parse_header("X-Debug:  on "),
+            Some(("X-Debug".to_string(), "on".to_string()))
+        );
+        assert_eq!(
+            http::parse_header("Accept:"),
+            Some(("Accept".to_string(), String::new()))
+        );
+        assert_eq!(http::parse_header("no colon"), None);
+        assert_eq!(http::parse_header(": value"), None);
+        assert_eq!(http::parse_header("Bad Name: value"), None);
+        assert_eq!(http::parse_header("X-Split: a\r\nInjected: b"), None);
+
+        let ok = b"HTTP/1.1 200 OK\r\n\r\n".to_vec();
+        let (url, server) = serve(vec![ok]);
+        let mut client = http::HttpClient::new();
+        client
+            .headers
+            .push(http::parse_header("X-Debug: on").unwrap());
+        client
+            .headers
+            .push(http::parse_header("user-agent: cli/1.0").unwrap());
+        client.get(&url).unwrap();
+        let request = String::from_utf8(server.join().unwrap().remove(0)).unwrap();
+        assert!(request.contains("\r\nX-Debug: on\r\n"));
+        assert!(request.contains("\r\nuser-agent: cli/1.0\r\n"));
+        assert!(!request.contains("Mozilla"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_transfer_codings() -> Result<(), String> {
+        let body = gzip(b"Hello");
+        let mut gzipped = format!(
+            "HTTP/1.1 200 OK\r\nTransfer-Encoding: gzip, chunked\r\n\r\n{:x}\r\n",
+            body.len()
+        )
+        .into_bytes();
+        gzipped.extend_from_slice(&body);
+        gzipped.extend_from_slice(b"\r\n0\r\n\r\n");
+        let unchunked = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: gzip\r\n\r\nHello".to_vec();
+        let unknown =
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: foo, chunked\r\n\r\n0\r\n\r\n".to_vec();
+        let (url, server) = serve(vec![gzipped, unchunked, unknown]);
+        let client = http::HttpClient::new();
+        assert_eq!(client.get(&url).unwrap().body, b"Hello");
+        for _ in 0..2 {
+            match client.get(&url) {
+                Err(http::RequestError::UnsupportedEncoding) => (),
+                other => panic!("expected an unsupported encoding, got {:?}", other),
+            }
+        }
+        server.join().unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn test_chunked_with_content_length() -> Result<(), String> {
+        let response = b"HTTP/1.1 200 OK\r\nContent-Length: 3\r\n\
+            Transfer-Encoding: chunked\r\n\r\n5\r\nHello\r\n6\r\n world\r\n0\r\n\r\n"
+            .to_vec();
+        let (url, server) = serve(vec![response]);
+        let http::Response { headers, body, .. } = http::HttpClient::new().get(&url).unwrap();
+        server.join().unwrap();
+        assert_eq!(body, b"Hello world");
+        assert!(!headers.contains_key("content-length"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_proxy_tunnel() -> Result<(), String> {
+        // The tunnel is refused, so the TLS handshake is never attempted
+        let (proxy_url, server) = serve(vec![b"HTTP/1.1 403 Forbidden\r\n\r\n".to_vec()]);
+        let mut client = http::HttpClient::new();
+        client.proxy = Some(http::Proxy::parse(&proxy_url).unwrap());
+        let result = client.get("https://example.com/");
+        let requests = server.join().unwrap();
+        assert!(requests[0].starts_with(b"CONNECT example.com:443 HTTP/1.1\r\n"));
+        match result {
+            Err(http::RequestError::StatusError(status, _)) => assert_eq!(status, "403"),
+            _ => panic!("expected the tunnel to be refused"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode_bom() -> Result<(), String> {
+        let body = b"\xef\xbb\xbf<body>Hello</body>";
+        let text = http::decode(body, Some("text/html; charset=iso-8859-1"));
+        assert_eq!(http::lex(text.as_bytes()), "Hello");
+        let body = b"\xfe\xff\0H\0i";
+        assert_eq!(http::decode(body, Some("text/html")), "Hi");
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode_windows_1252() -> Result<(), String> {
+        let body = b"\x93hi\x94 \x80\x85 caf\xe9 \x81";
+        let expected = "\u{201c}hi\u{201d} \u{20ac}\u{2026} caf\u{e9} \u{81}";
+        for charset in &["windows-1252", "ISO-8859-1", "latin1", "us-ascii"] {
+            let content_type = format!("text/html; charset={}", charset);
+            assert_eq!(http::decode(body, Some(&content_type)), expected);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode_utf16() -> Result<(), String> {
+        let text = "<body>\u{c548}\u{b155} world</body>";
+        let le: Vec<u8> = text.encode_utf16().flat_map(u16::to_le_bytes).collect();
+        let be: Vec<u8> = text.encode_utf16().flat_map(u16::to_be_bytes).collect();
+        let body = [&b"\xff\xfe"[..], &le].concat();
+        assert_eq!(http::decode(&body, None), text);
+        assert_eq!(http::decode(&le, Some("text/html; charset=UTF-16LE")), text);
+        assert_eq!(http::decode(&be, Some("text/html; charset=utf-16be")), text);
+        let decoded = http::decode(&body, Some("text/html"));
+        assert_eq!(http::lex(decoded.as_bytes()), "\u{c548}\u{b155} world");
+        Ok(())
+    }
+
     #[test]
     fn test_lex() -> Result<(), String> {
         let origin = "<body key=value> test </BODY>";
@@ -484,6 +3962,817 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_attachment_filename() -> Result<(), String> {
+        let filename = http::attachment_filename("attachment; filename=\"report.pdf\"");
+        assert_eq!(filename.as_deref(), Some("report.pdf"));
+        let filename = http::attachment_filename("Attachment; FILENAME=../../etc/passwd");
+        assert_eq!(filename.as_deref(), Some("passwd"));
+        let filename = http::attachment_filename("attachment; filename=\".bashrc\"");
+        assert_eq!(filename.as_deref(), Some("download"));
+        let filename = http::attachment_filename("attachment");
+        assert_eq!(filename.as_deref(), Some("download"));
+        assert_eq!(http::attachment_filename("inline; filename=a.txt"), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_lex_runs() -> Result<(), String> {
+        // The byte-at-a-time loop lex used to run
+        fn lex_bytes(body: &[u8]) -> Vec<u8> {
+            let mut in_angle = false;
+            let mut out = Vec::new();
+            for &c in body {
+                match c {
+                    b'<' => in_angle = true,
+                    b'>' => in_angle = false,
+                    _ if !in_angle => out.push(c),
+                    _ => (),
+                }
+            }
+            out
+        }
+        let cases: &[&[u8]] = &[
+            b"",
+            b"plain text",
+            b"<b>bold</b> and <i>italic</i>",
+            b"a > b < c",
+            b"<<nested>> text",
+            b"unterminated <tag",
+            b">leading and trailing<",
+            "caf\u{e9} <em>\u{c5}</em>".as_bytes(),
+        ];
+        for &case in cases {
+            assert_eq!(http::lex(case).as_bytes(), &lex_bytes(case)[..]);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_lex_numeric_references() -> Result<(), String> {
+        assert_eq!(http::lex(b"<p>It&#39;s &#x41;&#X62;&#99;</p>"), "It's Abc");
+        assert_eq!(
+            http::lex(b"&#128512; &#xD800; &#0; &#x110000;"),
+            "\u{1f600} \u{fffd} \u{fffd} \u{fffd}"
+        );
+        assert_eq!(
+            http::lex(b"a &# b &#39 c &#x; d &#xzz;"),
+            "a &# b &#39 c &#x; d &#xzz;"
+        );
+        assert_eq!(http::lex(b"&&#38;#39;"), "&&#39;");
+        Ok(())
+    }
+
+    #[test]
+    fn test_lex_named_references() -> Result<(), String> {
+        let body = b"<p>Tom &amp; Jerry&nbsp;&copy; 2024 &quot;&lt;b&gt;&quot;</p>";
+        assert_eq!(http::lex(body), "Tom & Jerry \u{a9} 2024 \"<b>\"");
+        assert_eq!(
+            http::lex(b"&bogus; &amp &AMP; & ;&amp;lt;"),
+            "&bogus; &amp &AMP; & ;&lt;"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_lex_aria_hidden() -> Result<(), String> {
+        let body = b"<body>Menu <span aria-hidden=\"true\">\xe2\x98\xb0 <span>icon</span> \
+            </span>Home <div ARIA-HIDDEN='true'>decor<br>ation</div><img aria-hidden=\"true\">\
+            Page<p aria-hidden=\"false\"> text</p></body>";
+        let mut headers = http::Headers::new();
+        headers.insert("content-type".to_string(), "text/html".to_string());
+        let url = "http://example.com/";
+        assert_eq!(http::page_text(url, &headers, body), "Menu Home Page text");
+        // Hidden only from the dump; the page itself still shows it
+        let shown: String = http::page_runs(url, &headers, body)
+            .into_iter()
+            .map(|(text, _)| text)
+            .collect();
+        assert_eq!(shown, "Menu \u{2630} icon Home decorationPage text");
+        assert_eq!(http::lex(body), shown);
+        Ok(())
+    }
+
+    #[test]
+    fn test_lex_script_and_style() -> Result<(), String> {
+        let body = b"<body>Before<script type=\"text/javascript\">if (a < b && c > d) \
+            { document.write('<p>hidden</p>'); }</SCRIPT>middle<STYLE>p > a { color: red }\
+            </style >after<scripts>shown</scripts></body>";
+        assert_eq!(http::lex(body), "Beforemiddleaftershown");
+        assert_eq!(http::lex(b"text<script>never closed"), "text");
+        Ok(())
+    }
+
+    #[test]
+    fn test_lex_comments() -> Result<(), String> {
+        let body = b"<body>Hello<!-- <b>not</b> a -> b --> world<!----> \
+            <p>more<!--unterminated <p>text</p></body>";
+        assert_eq!(http::lex(body), "Hello world more");
+        assert_eq!(http::lex(b"<!-- x > y -->text"), "text");
+        assert_eq!(http::lex(b"a<!-->b"), "ab");
+        assert_eq!(http::lex(b"a<!--->b"), "ab");
+        Ok(())
+    }
+
+    #[test]
+    fn test_tokenize() -> Result<(), String> {
+        use http::Token::{Tag, Text};
+        assert_eq!(
+            http::tokenize(b"<b>hi</b> there"),
+            vec![
+                Tag("b".to_string()),
+                Text("hi".to_string()),
+                Tag("/b".to_string()),
+                Text(" there".to_string()),
+            ]
+        );
+        assert_eq!(
+            http::tokenize(b"<a href=\"/x\">b &amp; c</a><!-- > -->d<script>1 < 2</script>"),
+            vec![
+                Tag("a href=\"/x\"".to_string()),
+                Text("b & c".to_string()),
+                Tag("/a".to_string()),
+                Text("d".to_string()),
+                Tag("script".to_string()),
+                Tag("/script".to_string()),
+            ]
+        );
+        // Bytes that are not UTF-8 become replacement characters
+        assert_eq!(
+            http::tokenize(b"caf\xe9"),
+            vec![Text("caf\u{fffd}".to_string())]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve() -> Result<(), String> {
+        let base = "http://example.com/a/b/c?q=1#top";
+        let cases = vec![
+            ("https://example.org/x", "https://example.org/x"),
+            ("//example.org/x", "http://example.org/x"),
+            ("/x/y", "http://example.com/x/y"),
+            ("d", "http://example.com/a/b/d"),
+            ("../d", "http://example.com/a/d"),
+            ("./d/../e", "http://example.com/a/b/e"),
+            ("?q=2", "http://example.com/a/b/c?q=2"),
+            ("#bottom", "http://example.com/a/b/c?q=1#bottom"),
+            ("/x#frag", "http://example.com/x#frag"),
+        ];
+        for (location, expected) in cases {
+            assert_eq!(http::resolve(base, location), expected);
+        }
+        assert_eq!(
+            http::resolve("http://example.com", "x"),
+            "http://example.com/x"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_relative_redirect() -> Result<(), String> {
+        let (url, server) = serve(vec![
+            b"HTTP/1.1 302 Found\r\nLocation: /next#frag\r\n\r\n".to_vec(),
+            b"HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\n\r\nDone".to_vec(),
+        ]);
+        let http::Response { body, .. } = http::request(&url).unwrap();
+        let requests = server.join().unwrap();
+        assert_eq!(body, b"Done");
+        assert!(requests[1].starts_with(b"GET /next HTTP/1.1\r\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_redirect_without_path() -> Result<(), String> {
+        let (other, other_server) = serve(vec![
+            b"HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\nDone".to_vec(),
+            b"HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\nDone".to_vec(),
+        ]);
+        let other = other.trim_end_matches('/').to_string();
+        let (url, server) = serve(vec![format!(
+            "HTTP/1.1 301 Moved Permanently\r\nLocation: {}\r\n\r\n",
+            other
+        )
+        .into_bytes()]);
+        assert_eq!(http::request(&url).unwrap().body, b"Done");
+        assert_eq!(
+            http::request(&format!("{}?q=1", other)).unwrap().body,
+            b"Done"
+        );
+        server.join().unwrap();
+        let requests = other_server.join().unwrap();
+        assert!(requests[0].starts_with(b"GET / HTTP/1.1\r\n"));
+        assert!(requests[1].starts_with(b"GET /?q=1 HTTP/1.1\r\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_redirect_chain() -> Result<(), String> {
+        let (url, server) = serve(vec![
+            b"HTTP/1.1 301 Moved Permanently\r\nLocation: /new/path\r\n\r\n".to_vec(),
+            b"HTTP/1.1 307 Temporary Redirect\r\nLocation: next?page=2\r\n\r\n".to_vec(),
+            [
+                &b"HTTP/1.1 200 OK\r\nContent-Encoding: gzip\r\n\r\n"[..],
+                &gzip(b"Arrived"),
+            ]
+            .concat(),
+        ]);
+        let http::Response { body, .. } = http::request(&url).unwrap();
+        let requests = server.join().unwrap();
+        assert_eq!(body, b"Arrived");
+        assert!(requests[1].starts_with(b"GET /new/path HTTP/1.1\r\n"));
+        assert!(requests[2].starts_with(b"GET /new/next?page=2 HTTP/1.1\r\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_error_status() -> Result<(), String> {
+        let (url, server) = serve(vec![
+            b"HTTP/1.1 404 Not Found\r\nContent-Length: 9\r\n\r\nNo such p".to_vec(),
+            b"HTTP/1.1 500 Internal Server Error\r\n\r\n".to_vec(),
+        ]);
+        let response = http::request(&url).unwrap();
+        assert_eq!(
+            (response.status, response.explanation.as_str()),
+            (404, "Not Found")
+        );
+        assert_eq!(response.body, b"No such p");
+        let response = http::request(&url).unwrap();
+        assert_eq!(response.status, 500);
+        assert_eq!(response.explanation, "Internal Server Error");
+        assert!(response.body.is_empty());
+        server.join().unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn test_post_redirect() -> Result<(), String> {
+        let (url, server) = serve(vec![
+            b"HTTP/1.1 307 Temporary Redirect\r\nLocation: /again\r\n\r\n".to_vec(),
+            b"HTTP/1.1 303 See Other\r\nLocation: /result\r\n\r\n".to_vec(),
+            b"HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\nDone".to_vec(),
+        ]);
+        let response = http::post(&url, "application/x-www-form-urlencoded", b"q=1").unwrap();
+        assert_eq!(response.body, b"Done");
+        let requests = server.join().unwrap();
+        assert!(requests[0].starts_with(b"POST / HTTP/1.1\r\n"));
+        assert!(requests[0].ends_with(b"\r\n\r\nq=1"));
+        assert!(requests[1].starts_with(b"POST /again HTTP/1.1\r\n"));
+        assert!(requests[1].ends_with(b"\r\n\r\nq=1"));
+        assert!(requests[2].starts_with(b"GET /result HTTP/1.1\r\n"));
+        let request = String::from_utf8_lossy(&requests[2]);
+        assert!(!request.contains("Content-Length"));
+        assert!(!request.contains("Content-Type"));
+        Ok(())
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_get_json() -> Result<(), String> {
+        let (url, server) = serve(vec![
+            b"HTTP/1.1 200 OK\r\nContent-Type: application/json; charset=utf-8\r\n\r\n\
+                {\"primes\": [2, 3, 5]}"
+                .to_vec(),
+            b"HTTP/1.1 200 OK\r\nContent-Type: application/problem+json\r\n\r\n{".to_vec(),
+            b"HTTP/1.1 200 OK\r\nContent-Type: text/html\r\n\r\n{}".to_vec(),
+        ]);
+        let client = http::HttpClient::new();
+        let json: HashMap<String, Vec<u32>> = client.get_json(&url).unwrap();
+        assert_eq!(json["primes"], vec![2, 3, 5]);
+        match client.get_json::<HashMap<String, u32>>(&url) {
+            Err(http::RequestError::InvalidJson(_)) => (),
+            other => panic!("expected invalid JSON, got {:?}", other),
+        }
+        match client.get_json::<HashMap<String, u32>>(&url) {
+            Err(http::RequestError::NotJson(content_type)) => assert_eq!(content_type, "text/html"),
+            other => panic!("expected a non-JSON content type, got {:?}", other),
+        }
+        server.join().unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn test_created_location() -> Result<(), String> {
+        let (url, server) = serve(vec![
+            b"HTTP/1.1 201 Created\r\nLocation: /items/7\r\nContent-Length: 2\r\n\r\nok".to_vec(),
+            b"HTTP/1.1 300 Multiple Choices\r\nLocation: /a\r\nContent-Length: 4\r\n\r\npick"
+                .to_vec(),
+        ]);
+        let client = http::HttpClient::new();
+        let http::Response { headers, body, .. } =
+            client.post(&url, "text/plain", b"item").unwrap();
+        assert_eq!(headers["location"], "/items/7");
+        assert_eq!(body, b"ok");
+        let http::Response { headers, body, .. } = client.get(&url).unwrap();
+        assert_eq!(headers["location"], "/a");
+        assert_eq!(body, b"pick");
+        assert_eq!(server.join().unwrap().len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_no_redirect() -> Result<(), String> {
+        let (url, server) = serve(vec![
+            b"HTTP/1.1 302 Found\r\nLocation: /next\r\n\r\n".to_vec()
+        ]);
+        let mut client = http::HttpClient::new();
+        client.no_redirect = true;
+        let response = client.get(&url).unwrap();
+        assert_eq!(server.join().unwrap().len(), 1);
+        assert_eq!(
+            (response.status, response.explanation.as_str()),
+            (302, "Found")
+        );
+        assert_eq!(response.headers["location"], "/next");
+        Ok(())
+    }
+
+    #[test]
+    fn test_redirect_downgrade() -> Result<(), String> {
+        let mut client = http::HttpClient::new();
+        let (secure, insecure) = ("https://example.com/", "http://example.com/");
+        client.check_redirect(secure, insecure).unwrap();
+        client.strict_redirects = true;
+        match client.check_redirect(secure, insecure) {
+            Err(err @ http::RequestError::InsecureRedirect(_)) => assert_eq!(
+                err.to_string(),
+                "Refused insecure redirect to http://example.com/"
+            ),
+            other => panic!("expected the downgrade to be refused, got {:?}", other),
+        }
+        client
+            .check_redirect("HTTPS://example.com/", "HTTP://example.com/")
+            .unwrap_err();
+        client
+            .check_redirect(secure, "https://example.org/")
+            .unwrap();
+        client.check_redirect(insecure, secure).unwrap();
+        client
+            .check_redirect(insecure, "http://example.org/")
+            .unwrap();
+
+        // Same-scheme redirects between plain http pages are still followed
+        let (url, server) = serve(vec![
+            b"HTTP/1.1 302 Found\r\nLocation: /next\r\n\r\n".to_vec(),
+            b"HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\nDone".to_vec(),
+        ]);
+        assert_eq!(client.get(&url).unwrap().body, b"Done");
+        server.join().unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn test_redirect_to_local_scheme() -> Result<(), String> {
+        let path = std::env::temp_dir().join(format!("secret-{}.txt", std::process::id()));
+        std::fs::write(&path, "secret").unwrap();
+        let hop = format!(
+            "HTTP/1.1 302 Found\r\nLocation: file://{}\r\n\r\n",
+            path.display()
+        );
+        let (url, server) = serve(vec![hop.into_bytes()]);
+        let result = http::request(&url);
+        server.join().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        match result {
+            Err(http::RequestError::InsecureRedirect(_)) => (),
+            other => panic!("expected the redirect to be refused, got {:?}", other),
+        }
+        let client = http::HttpClient::new();
+        for to in &["data:text/plain,hi", "view-source:http://example.com/"] {
+            client
+                .check_redirect("http://example.com/", to)
+                .unwrap_err();
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_too_many_redirects() -> Result<(), String> {
+        let hop = b"HTTP/1.1 302 Found\r\nLocation: /loop\r\n\r\n".to_vec();
+        let (url, server) = serve(vec![hop; http::MAX_REDIRECTS + 1]);
+        let result = http::request(&url);
+        let requests = server.join().unwrap();
+        assert_eq!(requests.len(), http::MAX_REDIRECTS + 1);
+        match result {
+            Err(err @ http::RequestError::TooManyRedirects) => {
+                assert_eq!(err.to_string(), "Too many redirects")
+            }
+            _ => panic!("expected too many redirects"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_url_filename() -> Result<(), String> {
+        assert_eq!(
+            http::url_filename("http://example.com/a/page.html"),
+            "page.html"
+        );
+        assert_eq!(
+            http::url_filename("https://example.com/file.txt?v=2#top"),
+            "file.txt"
+        );
+        assert_eq!(http::url_filename("http://example.com/dir/"), "index.html");
+        assert_eq!(http::url_filename("http://example.com"), "index.html");
+        assert_eq!(http::url_filename("http://example.com/a/.."), "index.html");
+        assert_eq!(http::url_filename(""), "index.html");
+        assert_eq!(http::url_filename("http://example.com/.bashrc"), "bashrc");
+        assert_eq!(http::url_filename("http://example.com/..."), "index.html");
+        assert_eq!(http::url_filename("data:text/html,<p>hi</p>"), "index.html");
+        assert_eq!(http::url_filename("http://example.com/a:b"), "index.html");
+        assert_eq!(http::url_filename("http://example.com/a\tb"), "index.html");
+        Ok(())
+    }
+
+    #[test]
+    fn test_create_unique() -> Result<(), String> {
+        let dir = std::env::temp_dir().join(format!("save-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("page.html");
+        std::fs::write(&path, "original").unwrap();
+        let path = path.to_str().unwrap();
+        let (_, first) = display::create_unique(path).unwrap();
+        let (_, second) = display::create_unique(path).unwrap();
+        let (_, plain) = display::create_unique(dir.join("README").to_str().unwrap()).unwrap();
+        let contents = std::fs::read(path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(contents, b"original");
+        assert_eq!(first, dir.join("page-1.html").to_str().unwrap());
+        assert_eq!(second, dir.join("page-2.html").to_str().unwrap());
+        assert_eq!(plain, dir.join("README").to_str().unwrap());
+        Ok(())
+    }
+
+    #[test]
+    fn test_layout_wraps_words() -> Result<(), String> {
+        let paragraph = "Lorem ipsum dolor sit amet, consectetur adipiscing elit, sed do \
+            eiusmod tempor incididunt ut labore et dolore magna aliqua. "
+            .repeat(10);
+        let measure = |word: &str, _| word.len() as i32 * 7;
+        let runs = vec![(paragraph.clone(), http::Style::default())];
+        let (words, max_scroll) = display::layout(&runs, measure);
+        let texts: Vec<&str> = words.iter().map(|word| word.text.as_str()).collect();
+        assert_eq!(texts, paragraph.split_whitespace().collect::<Vec<_>>());
+        let right = display::BrowserWidget::get_width() as i32 - 13;
+        assert!(words
+            .iter()
+            .all(|word| word.x + measure(&word.text, word.style) <= right));
+        assert!(words.iter().filter(|word| word.x == 13).count() > 1);
+        assert_eq!(max_scroll, words.last().unwrap().y);
+
+        // A word too long for any line starts a new one and breaks
+        // between characters
+        let text = format!("a\nb {} c", "x".repeat(200));
+        let runs = vec![(text, http::Style::default())];
+        let (words, max_scroll) = display::layout(&runs, measure);
+        let positions: Vec<(i32, i32, usize)> = words
+            .iter()
+            .map(|word| (word.x, word.y, word.text.len()))
+            .collect();
+        assert_eq!(
+            positions,
+            vec![
+                (13, 12, 1),
+                (13, 24, 1),
+                (13, 36, 110),
+                (13, 48, 90),
+                (650, 48, 1)
+            ]
+        );
+        assert_eq!(max_scroll, 48);
+
+        // Unspaced CJK text wraps at character boundaries too
+        let text = "\u{d55c}\u{ae00}".repeat(100);
+        let measure = |word: &str, _| word.chars().count() as i32 * 12;
+        let runs = vec![(text.clone(), http::Style::default())];
+        let (words, _) = display::layout(&runs, measure);
+        assert!(words.len() > 1);
+        assert!(words
+            .iter()
+            .all(|word| word.x + measure(&word.text, word.style) <= right));
+        let joined: String = words.iter().map(|word| word.text.as_str()).collect();
+        assert_eq!(joined, text);
+        Ok(())
+    }
+
+    #[test]
+    fn test_lex_styled() -> Result<(), String> {
+        let plain = http::Style::default();
+        let bold = http::Style {
+            bold: true,
+            italic: false,
+        };
+        let both = http::Style {
+            bold: true,
+            italic: true,
+        };
+        let italic = http::Style {
+            bold: false,
+            italic: true,
+        };
+        let runs = http::lex_styled(b"<p>a <B>b<i>c</i></b> <em>d</em></p>");
+        let expected = vec![
+            ("a ".to_string(), plain),
+            ("b".to_string(), bold),
+            ("c".to_string(), both),
+            (" ".to_string(), plain),
+            ("d".to_string(), italic),
+        ];
+        assert_eq!(runs, expected);
+        // An unclosed <b> ends with the paragraph around it
+        let runs = http::lex_styled(b"<p>x <b>bold<br>still</p><p>plain</p>");
+        let expected = vec![
+            ("x ".to_string(), plain),
+            ("boldstill".to_string(), bold),
+            ("plain".to_string(), plain),
+        ];
+        assert_eq!(runs, expected);
+        assert_eq!(http::lex(b"<p>a <b>b<i>c</i></b></p>"), "a bc");
+        Ok(())
+    }
+
+    #[test]
+    fn test_layout_styles() -> Result<(), String> {
+        let bold = http::Style {
+            bold: true,
+            italic: false,
+        };
+        let runs = vec![
+            ("a bo".to_string(), http::Style::default()),
+            ("ld c".to_string(), bold),
+        ];
+        // Bold is wider, and a word in two styles stays together
+        let measure =
+            |word: &str, style: http::Style| word.len() as i32 * if style.bold { 8 } else { 7 };
+        let (words, _) = display::layout(&runs, measure);
+        let words: Vec<_> = words
+            .iter()
+            .map(|word| (word.text.as_str(), word.x, word.style.bold))
+            .collect();
+        assert_eq!(
+            words,
+            vec![
+                ("a", 13, false),
+                ("bo", 27, false),
+                ("ld", 41, true),
+                ("c", 65, true)
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_scroll_into_view() -> Result<(), String> {
+        let mut widget = display::BrowserWidget::new("line\n".repeat(200));
+        let target = druid::Rect::new(13.0, 1200.0, 100.0, 1212.0);
+        let scroll = widget.scroll_into_view(target) as f64;
+        let height = display::BrowserWidget::get_height();
+        assert!(target.y0 >= scroll + 50.0);
+        assert!(target.y1 <= scroll + height - 50.0);
+
+        // A rect that is already comfortably visible leaves the scroll alone
+        let visible = druid::Rect::new(13.0, scroll + 100.0, 100.0, scroll + 112.0);
+        assert_eq!(widget.scroll_into_view(visible) as f64, scroll);
+
+        // One taller than the viewport has its top padded by the margin
+        let tall = druid::Rect::new(13.0, 1000.0, 100.0, 1000.0 + 2.0 * height);
+        assert_eq!(widget.scroll_into_view(tall), 950);
+        Ok(())
+    }
+
+    #[test]
+    fn test_find() -> Result<(), String> {
+        let text = format!(
+            "{}Needle{}needles",
+            "line\n".repeat(100),
+            "\nline".repeat(100)
+        );
+        let mut widget = display::BrowserWidget::new(text);
+        assert_eq!(widget.find_key(&druid::KbKey::Enter), None);
+        widget.open_find();
+        for key in ["n", "E", "e", "x"] {
+            widget.find_key(&druid::KbKey::Character(key.to_string()));
+        }
+        widget.find_key(&druid::KbKey::Backspace);
+        assert_eq!(widget.find_text(), Some("nEe"));
+        // The first match, on line 101, lands inside the viewport with room
+        // to spare
+        let scroll = widget.find_key(&druid::KbKey::Enter).unwrap() as f64;
+        let height = display::BrowserWidget::get_height();
+        let y = 101.0 * 12.0;
+        assert!(y >= scroll + 50.0);
+        assert!(y + 12.0 <= scroll + height - 50.0);
+        // Further matches come in order and then start over
+        let last = widget.find_key(&druid::KbKey::Enter).unwrap();
+        assert!(last as f64 > scroll);
+        assert_eq!(
+            widget.find_key(&druid::KbKey::Enter).unwrap() as f64,
+            scroll
+        );
+        assert_eq!(widget.find_next("missing"), None);
+        widget.find_key(&druid::KbKey::Escape);
+        assert_eq!(widget.find_text(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_reload_bypass_cache() -> Result<(), String> {
+        let response = b"HTTP/1.1 200 OK\r\n\r\nHello".to_vec();
+        let (url, server) = serve(vec![response.clone(), response]);
+        let mut widget =
+            display::BrowserWidget::with_url(http::HttpClient::new(), &url, Vec::new(), Vec::new());
+        widget.reload(false).unwrap();
+        widget.reload(true).unwrap();
+        let requests = server.join().unwrap();
+        let requests: Vec<_> = requests
+            .iter()
+            .map(|r| String::from_utf8_lossy(r))
+            .collect();
+        assert!(!requests[0].contains("Cache-Control"));
+        assert!(requests[1].contains("\r\nCache-Control: no-cache\r\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_dark_theme() -> Result<(), String> {
+        let mut widget = display::BrowserWidget::new("Hello".to_string());
+        let light = widget.theme().clone();
+        assert_eq!(light, display::Theme::light());
+        widget.set_theme(display::Theme::dark());
+        let dark = widget.theme();
+        assert_eq!(dark.foreground, light.background);
+        assert_ne!(dark.background, light.background);
+        assert_ne!(dark.foreground, light.foreground);
+        Ok(())
+    }
+
+    #[test]
+    fn test_history() -> Result<(), String> {
+        let path = std::env::temp_dir().join(format!("history-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        {
+            let mut history = history::History::open(&path).unwrap();
+            assert!(history.visits().is_empty());
+            history.add_at("https://example.com/", 100).unwrap();
+            history.add_at("https://www.rust-lang.org/", 200).unwrap();
+            history.add_at("https://example.com/about", 300).unwrap();
+            history.add_at("https://example.com/", 400).unwrap();
+        }
+        history::History::append(&path, "https://example.org/").unwrap();
+        let history = history::History::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(history.visits().len(), 5);
+        assert_eq!(history.visits()[4].url, "https://example.org/");
+        assert_eq!(
+            history.visits()[1],
+            history::Visit {
+                url: "https://www.rust-lang.org/".to_string(),
+                time: 200
+            }
+        );
+        assert_eq!(
+            history.query("https://example.com"),
+            vec!["https://example.com/", "https://example.com/about"]
+        );
+        assert_eq!(history.query("rust"), vec!["https://www.rust-lang.org/"]);
+        assert!(history.query("nothing").is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_history_suggest() -> Result<(), String> {
+        let mut history = history::History::new();
+        history.add_at("https://example.com/a", 100).unwrap();
+        history.add_at("https://example.com/b", 200).unwrap();
+        history.add_at("https://example.com/a", 300).unwrap();
+        history.add_at("https://example.com/c", 400).unwrap();
+        history.add_at("https://other.org/", 500).unwrap();
+        assert_eq!(
+            history.suggest("example", 10),
+            vec![
+                "https://example.com/a",
+                "https://example.com/c",
+                "https://example.com/b"
+            ]
+        );
+        assert_eq!(
+            history.suggest("https://ex", 1),
+            vec!["https://example.com/a"]
+        );
+        assert!(history.suggest("missing", 10).is_empty());
+        // Schemes and hosts are case-insensitive, paths are not
+        history.add_at("https://Example.com/About", 600).unwrap();
+        assert_eq!(
+            history.suggest("HTTPS://EXAMPLE.COM/A", 10),
+            vec!["https://Example.com/About"]
+        );
+        assert_eq!(
+            history.suggest("Exa", 2),
+            vec!["https://example.com/a", "https://Example.com/About"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_address_bar() -> Result<(), String> {
+        let path = std::env::temp_dir().join(format!("address-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        {
+            let mut history = history::History::open(&path).unwrap();
+            history.add_at("https://example.com/", 100).unwrap();
+            history.add_at("https://example.org/", 200).unwrap();
+            history.add_at("https://rust-lang.org/", 300).unwrap();
+        }
+        let mut widget = display::BrowserWidget::new("text".to_string());
+        widget.set_history_path(path.clone());
+        assert_eq!(widget.address(), None);
+        widget.open_address_bar();
+        std::fs::remove_file(&path).unwrap();
+        let suggestions = [
+            "https://example.org/".to_string(),
+            "https://example.com/".to_string(),
+        ];
+        assert_eq!(widget.address(), Some(("", &[][..])));
+        for key in ["E", "x"] {
+            assert_eq!(
+                widget.address_key(&druid::KbKey::Character(key.to_string())),
+                None
+            );
+        }
+        assert_eq!(widget.address(), Some(("Ex", &suggestions[..])));
+        widget.address_key(&druid::KbKey::ArrowDown);
+        widget.address_key(&druid::KbKey::ArrowDown);
+        widget.address_key(&druid::KbKey::ArrowDown);
+        assert_eq!(
+            widget.address(),
+            Some(("https://example.com/", &suggestions[..]))
+        );
+        widget.address_key(&druid::KbKey::ArrowUp);
+        assert_eq!(
+            widget.address(),
+            Some(("https://example.org/", &suggestions[..]))
+        );
+        // Editing starts from the picked suggestion
+        widget.address_key(&druid::KbKey::Backspace);
+        assert_eq!(
+            widget.address(),
+            Some(("https://example.org", &suggestions[..1]))
+        );
+        assert_eq!(
+            widget.address_key(&druid::KbKey::Enter),
+            Some("https://example.org".to_string())
+        );
+        assert_eq!(widget.address(), None);
+        widget.open_address_bar();
+        widget.address_key(&druid::KbKey::Escape);
+        assert_eq!(widget.address(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_natural_scroll() -> Result<(), String> {
+        let mut widget = display::BrowserWidget::new("line\n".repeat(200));
+        widget.set_scroll_step(30);
+        assert_eq!(widget.wheel(1.0), 30);
+        assert_eq!(widget.wheel(1.0), 60);
+        assert_eq!(widget.wheel(-1.0), 30);
+        widget.set_natural_scroll(true);
+        assert_eq!(widget.wheel(1.0), 0);
+        assert_eq!(widget.wheel(1.0), 0);
+        assert_eq!(widget.wheel(-1.0), 30);
+        // Even a step the command line would refuse stays in the page
+        widget.set_scroll_step(-100);
+        assert_eq!(widget.wheel(-1.0), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_color() -> Result<(), String> {
+        let rgba = |s| display::parse_color(s).map(|color| color.as_rgba8());
+        assert_eq!(rgba("Red"), Some((255, 0, 0, 255)));
+        assert_eq!(rgba("transparent"), Some((0, 0, 0, 0)));
+        assert_eq!(rgba("#0f8"), Some((0, 255, 136, 255)));
+        assert_eq!(rgba("#0f88"), Some((0, 255, 136, 136)));
+        assert_eq!(rgba("#102030"), Some((16, 32, 48, 255)));
+        assert_eq!(rgba("#10203040"), Some((16, 32, 48, 64)));
+        assert_eq!(rgba("rgb(10, 20, 30)"), Some((10, 20, 30, 255)));
+        assert_eq!(rgba("rgb(100%, 0%, 50%)"), Some((255, 0, 128, 255)));
+        assert_eq!(rgba("rgba(10, 20, 30, 0.5)"), Some((10, 20, 30, 128)));
+        assert_eq!(rgba("rgb(10 20 30 / 50%)"), Some((10, 20, 30, 128)));
+        assert_eq!(rgba("rgb(300, -20, 30)"), Some((255, 0, 30, 255)));
+        assert_eq!(rgba("rgba(0, 0, 0, 2)"), Some((0, 0, 0, 255)));
+        assert_eq!(rgba("hsl(0, 100%, 50%)"), Some((255, 0, 0, 255)));
+        assert_eq!(rgba("hsl(120deg, 100%, 25%)"), Some((0, 128, 0, 255)));
+        assert_eq!(rgba("hsla(240, 100%, 50%, 0.25)"), Some((0, 0, 255, 64)));
+        assert_eq!(rgba("hsl(-120, 150%, 50%)"), Some((0, 0, 255, 255)));
+        assert_eq!(rgba("#12345"), None);
+        assert_eq!(rgba("#ggg"), None);
+        assert_eq!(rgba("rgb(1, 2)"), None);
+        assert_eq!(rgba("hsl(0, 100, 50)"), None);
+        assert_eq!(rgba("notacolor"), None);
+        Ok(())
+    }
+
     #[test]
     fn test_redirect() -> Result<(), String> {
         let redirect_sites = vec![
@@ -491,8 +4780,8 @@ mod tests {
             "http://browser.engineering/redirect",
         ];
         for site in redirect_sites {
-            let (header, body) = http::request(site).unwrap();
-            assert!(header.contains_key("content-type"));
+            let http::Response { headers, body, .. } = http::request(site).unwrap();
+            assert!(headers.contains_key("content-type"));
             assert!(!body.is_empty());
         }
         Ok(())
@@ -1,15 +1,84 @@
+use clap::{App, Arg, ArgMatches};
 use druid::{AppLauncher, LocalizedString, WindowDesc};
-use lib::display::BrowserWidget;
-use lib::http::{lex, request};
+use lib::display::{create_unique, BrowserWidget, Theme};
+use lib::history::History;
+use lib::http::{
+    attachment_filename, page_runs, page_text, parse_header, HttpClient, Proxy, Response, Style,
+};
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
 
 const APP_NAME: &str = "Browser-engineering";
-struct BrowserApplication {}
+struct BrowserApplication {
+    client: HttpClient,
+    // Save the body to this file instead of rendering it
+    output: Option<String>,
+    // Content type and body to POST instead of issuing a GET
+    post: Option<(String, String)>,
+    // Print the page text to stdout instead of opening a window
+    dump_text: bool,
+    theme: Theme,
+    scroll_step: Option<i32>,
+    natural_scroll: bool,
+}
 
 impl BrowserApplication {
-    fn run(&self, url: &str) {
-        let (_headers, body) = request(&url).unwrap_or_else(|e| panic!("{}", e));
-        let text = lex(&body);
-        let browser_widget = || -> BrowserWidget { BrowserWidget::new(text) };
+    fn run(self, url: &str) {
+        let response = match &self.post {
+            Some((content_type, data)) => self.client.post(url, content_type, data.as_bytes()),
+            None => self.client.get_with_progress(url, &mut |received, total| {
+                match total {
+                    Some(total) => eprint!("\rDownloading {} / {} bytes", received, total),
+                    None => eprint!("\rDownloading {} bytes", received),
+                }
+                io::stderr().flush().ok();
+            }),
+        };
+        let response = response.unwrap_or_else(|e| panic!("{}", e));
+        eprintln!();
+        if response.status >= 300 {
+            eprintln!("{} {}", response.status, response.explanation);
+        }
+        // Only seen with --no-redirect
+        if let (300..=399, Some(location)) = (response.status, response.headers.get("location")) {
+            eprintln!("Location: {}", location);
+        }
+        let Response { headers, body, .. } = response;
+        let attachment = headers
+            .get("content-disposition")
+            .and_then(|value| attachment_filename(value));
+        if let Some(path) = &self.output {
+            fs::write(path, &body).unwrap_or_else(|e| panic!("{}: {}", path, e));
+            println!("Saved to {}", path);
+            return;
+        }
+        // The server chose this name, so never replace an existing file
+        if let Some(name) = attachment {
+            match create_unique(&name).and_then(|(mut file, path)| {
+                file.write_all(&body)?;
+                Ok(path)
+            }) {
+                Ok(path) => println!("Saved to {}", path),
+                Err(e) => eprintln!("Could not save {}: {}", name, e),
+            }
+            return;
+        }
+        if self.dump_text {
+            print!("{}", page_text(url, &headers, &body));
+            return;
+        }
+        // Only pages opened in the window count as visits, not scripted
+        // fetches
+        let history_path = History::default_path();
+        if let Some(path) = &history_path {
+            if let Err(e) = History::append(path, url) {
+                eprintln!("Could not save history to {}: {}", path.display(), e);
+            }
+        }
+        let runs = page_runs(url, &headers, &body);
+        let url = url.to_string();
+        let browser_widget = move || self.widget(&url, body, runs, history_path);
         let window = WindowDesc::new(browser_widget)
             .title(LocalizedString::new(APP_NAME))
             .window_size((BrowserWidget::get_width(), BrowserWidget::get_height()));
@@ -18,17 +87,193 @@ impl BrowserApplication {
             .launch(0)
             .expect("launch failed");
     }
+
+    // The widget showing runs of body, fetched from url, set up as the
+    // command line asked
+    fn widget(
+        self,
+        url: &str,
+        body: Vec<u8>,
+        runs: Vec<(String, Style)>,
+        history_path: Option<PathBuf>,
+    ) -> BrowserWidget {
+        let mut widget = BrowserWidget::with_url(self.client, url, body, runs);
+        widget.set_theme(self.theme);
+        if let Some(scroll_step) = self.scroll_step {
+            widget.set_scroll_step(scroll_step);
+        }
+        widget.set_natural_scroll(self.natural_scroll);
+        if let Some(path) = history_path {
+            widget.set_history_path(path);
+        }
+        widget
+    }
 }
 
-pub fn main() {
-    use clap::{App, Arg};
-    let matches = App::new(APP_NAME)
+fn cli() -> App<'static, 'static> {
+    App::new(APP_NAME)
         .arg(Arg::with_name("url").value_name("URL").takes_value(true))
-        .get_matches();
+        .arg(
+            Arg::with_name("output")
+                .long("output")
+                .value_name("FILE")
+                .help("Save the response body to FILE instead of rendering it")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("cookie")
+                .long("cookie")
+                .value_name("NAME=VALUE")
+                .help("Send a cookie to the host of URL; may be repeated")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("header")
+                .long("header")
+                .value_name("NAME: VALUE")
+                .help("Send an extra request header; may be repeated")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("post-data")
+                .long("post-data")
+                .value_name("BODY")
+                .help("POST BODY to URL instead of issuing a GET")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("content-type")
+                .long("content-type")
+                .value_name("TYPE")
+                .help("Content type of --post-data")
+                .takes_value(true)
+                .default_value("application/x-www-form-urlencoded"),
+        )
+        .arg(
+            Arg::with_name("dump-text")
+                .long("dump-text")
+                .help("Print the page text to stdout instead of opening a window"),
+        )
+        .arg(
+            Arg::with_name("proxy")
+                .long("proxy")
+                .value_name("URL")
+                .help("Send requests through the HTTP proxy at URL")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("accept-encoding")
+                .long("accept-encoding")
+                .value_name("CODINGS")
+                .help("Send CODINGS as Accept-Encoding, e.g. identity")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("no-redirect")
+                .long("no-redirect")
+                .help("Show redirects instead of following them"),
+        )
+        .arg(
+            Arg::with_name("strict-redirects")
+                .long("strict-redirects")
+                .help("Refuse redirects from https to http"),
+        )
+        .arg(
+            Arg::with_name("dark")
+                .long("dark")
+                .help("Render light text on a dark background"),
+        )
+        .arg(
+            Arg::with_name("scroll-step")
+                .long("scroll-step")
+                .value_name("PIXELS")
+                .help("Distance scrolled by one wheel step")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("natural-scroll")
+                .long("natural-scroll")
+                .help("Invert the wheel direction"),
+        )
+}
+
+// The application for matches of cli(), fetching url
+fn application(matches: &ArgMatches, url: &str) -> BrowserApplication {
+    let mut client = HttpClient::new();
+    if let Some(proxy) = matches.value_of("proxy") {
+        let proxy =
+            Proxy::parse(proxy).unwrap_or_else(|e| panic!("Invalid proxy {}: {}", proxy, e));
+        client.proxy = Some(proxy);
+    }
+    if let Some(codings) = matches.value_of("accept-encoding") {
+        client
+            .set_accept_encoding(codings)
+            .unwrap_or_else(|e| panic!("Invalid accept encoding {}: {}", codings, e));
+    }
+    client.no_redirect = matches.is_present("no-redirect");
+    client.strict_redirects = matches.is_present("strict-redirects");
+    for header in matches.values_of("header").into_iter().flatten() {
+        let header = parse_header(header).unwrap_or_else(|| panic!("Malformed header: {}", header));
+        client.headers.push(header);
+    }
+    for cookie in matches.values_of("cookie").into_iter().flatten() {
+        if !client.cookies.borrow_mut().add(url, cookie) {
+            panic!("Malformed cookie: {}", cookie);
+        }
+    }
+
+    BrowserApplication {
+        client,
+        output: matches.value_of("output").map(str::to_string),
+        post: matches.value_of("post-data").map(|data| {
+            let content_type = matches.value_of("content-type").unwrap();
+            (content_type.to_string(), data.to_string())
+        }),
+        dump_text: matches.is_present("dump-text"),
+        theme: if matches.is_present("dark") {
+            Theme::dark()
+        } else {
+            Theme::light()
+        },
+        scroll_step: matches.value_of("scroll-step").map(|step| {
+            step.parse()
+                .ok()
+                .filter(|&step: &i32| step > 0)
+                .unwrap_or_else(|| panic!("Invalid scroll step: {}", step))
+        }),
+        natural_scroll: matches.is_present("natural-scroll"),
+    }
+}
+
+pub fn main() {
+    let matches = cli().get_matches();
     let url = matches
         .value_of("url")
         .expect("required argument at the moment");
+    application(&matches, url).run(url);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    let app = BrowserApplication {};
-    app.run(url);
+    // The widget a command line with args would open on a blank page
+    fn widget(args: &[&str]) -> BrowserWidget {
+        let url = "http://example.com/";
+        let matches = cli().get_matches_from([&["browser"], args, &[url]].concat());
+        application(&matches, url).widget(url, Vec::new(), Vec::new(), None)
+    }
+
+    #[test]
+    fn test_dark_theme() -> Result<(), String> {
+        assert_eq!(widget(&[]).theme(), &Theme::light());
+        let dark = widget(&["--dark"]);
+        assert_eq!(dark.theme().background, Theme::dark().background);
+        assert_eq!(dark.theme().foreground, Theme::dark().foreground);
+        Ok(())
+    }
 }
@@ -0,0 +1,93 @@
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::process::Command;
+use std::thread;
+
+// Answer one request on a local port with an HTML page echoing the request
+// body. Returns the URL to fetch and a handle that yields the raw request.
+fn echo_server() -> (String, thread::JoinHandle<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/submit", listener.local_addr().unwrap());
+    let handle = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = Vec::new();
+        let mut buf = [0u8; 1024];
+        loop {
+            let len = stream.read(&mut buf).unwrap();
+            request.extend_from_slice(&buf[..len]);
+            let text = String::from_utf8_lossy(&request).to_string();
+            let head_len = match text.find("\r\n\r\n") {
+                Some(end) => end + 4,
+                None if len == 0 => break,
+                None => continue,
+            };
+            let length = text
+                .to_ascii_lowercase()
+                .split("\r\ncontent-length: ")
+                .nth(1)
+                .map_or(0, |rest| {
+                    rest.split("\r\n").next().unwrap().parse().unwrap()
+                });
+            if len == 0 || request.len() >= head_len + length {
+                break;
+            }
+        }
+        let request = String::from_utf8(request).unwrap();
+        let body = request.split("\r\n\r\n").nth(1).unwrap_or("");
+        let page = format!("<body><p>Got <b>{}</b></p></body>", body);
+        write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: {}\r\n\r\n{}",
+            page.len(),
+            page
+        )
+        .unwrap();
+        request
+    });
+    (url, handle)
+}
+
+#[test]
+fn test_post_data() {
+    let (url, server) = echo_server();
+    let output = Command::new(env!("CARGO_BIN_EXE_browser"))
+        .args([
+            "--post-data",
+            "name=value",
+            "--content-type",
+            "text/plain",
+            "--dump-text",
+            &url,
+        ])
+        .output()
+        .unwrap();
+    // Check the exit first: the server never returns if nothing connected
+    assert!(output.status.success(), "{:?}", output);
+    let request = server.join().unwrap();
+    assert!(request.starts_with("POST /submit HTTP/1.1\r\n"));
+    assert!(request.contains("\r\nContent-Type: text/plain\r\n"));
+    assert!(request.contains("\r\nContent-Length: 10\r\n"));
+    assert!(request.ends_with("\r\n\r\nname=value"));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "Got name=value");
+}
+
+#[test]
+fn test_extra_headers() {
+    let (url, server) = echo_server();
+    let output = Command::new(env!("CARGO_BIN_EXE_browser"))
+        .args([
+            "--header",
+            "X-Debug: on",
+            "--header",
+            "Accept-Language:ko",
+            "--dump-text",
+            &url,
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let request = server.join().unwrap();
+    assert!(request.starts_with("GET /submit HTTP/1.1\r\n"));
+    assert!(request.contains("\r\nX-Debug: on\r\n"));
+    assert!(request.contains("\r\nAccept-Language: ko\r\n"));
+}